@@ -0,0 +1,32 @@
+#!/bin/sh
+# Vendors TypeScript's diagnostic messages for the version pinned in
+# catalog/TYPESCRIPT_VERSION (or the one given), then regenerates the catalog:
+#
+#   scripts/update-typescript.sh [VERSION]
+#
+# The English messages come from the TypeScript repository at that tag, and
+# the localized ones from the npm package, for each catalog/locales/*.json.
+set -eu
+
+cd "$(dirname "$0")/.."
+version=${1:-$(cat catalog/TYPESCRIPT_VERSION)}
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+echo "Fetching TypeScript $version"
+curl -fsSL -o "$tmp/diagnosticMessages.json" \
+    "https://raw.githubusercontent.com/microsoft/TypeScript/v$version/src/compiler/diagnosticMessages.json"
+curl -fsSL "https://registry.npmjs.org/typescript/-/typescript-$version.tgz" | tar -xz -C "$tmp"
+
+cp "$tmp/diagnosticMessages.json" catalog/diagnosticMessages.json
+for file in catalog/locales/*.json; do
+    locale=$(basename "$file" .json)
+    cp "$tmp/package/lib/$locale/diagnosticMessages.generated.json" "$file"
+done
+echo "$version" > catalog/TYPESCRIPT_VERSION
+
+cargo run --quiet -- generate-catalog
+for file in catalog/locales/*.json; do
+    cargo run --quiet -- generate-catalog --locale "$(basename "$file" .json)"
+done
+cargo run --quiet -- check-catalog --messages catalog/diagnosticMessages.json
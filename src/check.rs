@@ -0,0 +1,146 @@
+use crate::errors::{extract_params, placeholders, Catalog, ErrorInfo, KnownMessage, PLACEHOLDER};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// Validates every entry of `catalog`, optionally against TypeScript's
+/// `diagnosticMessages.json`, printing what it finds. Returns the number of problems.
+pub fn check_catalog<W: Write>(
+    catalog: &Catalog,
+    known: Option<&BTreeMap<u32, KnownMessage>>,
+    output: &mut W,
+) -> std::io::Result<usize> {
+    let entries = catalog.entries();
+    let mut problems = 0;
+    let mut missing = Vec::new();
+
+    for (code, info) in &entries {
+        let mut found = check_entry(info);
+        match known.map(|known| (known.get(code), known)) {
+            Some((Some(message), _)) if message.pattern == info.pattern_text => {}
+            Some((Some(message), known)) => {
+                let mut problem = format!(
+                    "pattern differs from TypeScript's message\n    catalog:    {}\n    TypeScript: {}",
+                    info.pattern_text, message.pattern
+                );
+                if let Some(other) = known.values().find(|m| m.pattern == info.pattern_text) {
+                    problem.push_str(&format!("\n    (the catalog's pattern is TS{}'s message)", other.code));
+                }
+                found.push(problem);
+            }
+            Some((None, _)) => missing.push(format!("TS{}", code)),
+            None => {}
+        }
+
+        for problem in &found {
+            writeln!(output, "TS{}: {}", code, problem)?;
+        }
+        problems += found.len();
+    }
+
+    if !missing.is_empty() {
+        writeln!(output, "note: not in diagnosticMessages.json: {}", missing.join(", "))?;
+    }
+    writeln!(output, "{} entries checked, {} problem(s)", entries.len(), problems)?;
+    Ok(problems)
+}
+
+fn check_entry(info: &ErrorInfo) -> Vec<String> {
+    let mut problems = Vec::new();
+    let occurrences = placeholders(&info.pattern_text);
+    let captured: BTreeSet<usize> = occurrences.iter().copied().collect();
+
+    for (field, text) in [("message", Some(info.message.as_str())), ("body", info.body.as_deref())] {
+        let Some(text) = text else {
+            continue;
+        };
+        let unknown: BTreeSet<usize> = placeholders(text).into_iter().filter(|i| !captured.contains(i)).collect();
+        if !unknown.is_empty() {
+            problems.push(format!(
+                "{} uses {}, but the pattern only captures {}",
+                field,
+                list_placeholders(&unknown),
+                if captured.is_empty() { "nothing".to_string() } else { list_placeholders(&captured) }
+            ));
+        }
+    }
+    if info.message.contains("{{") || info.message.contains("}}") {
+        problems.push("message contains doubled braces, which are shown literally".to_string());
+    }
+
+    // Fill the pattern with distinct values and make sure they come back out.
+    let sample = PLACEHOLDER.replace_all(&info.pattern_text, |caps: &regex::Captures| format!("<p{}>", &caps[1]));
+    let expected: Vec<String> = occurrences.iter().map(|i| format!("<p{}>", i)).collect();
+    match extract_params(&info.pattern, &sample) {
+        Some(params) if params == expected => {}
+        Some(params) => problems.push(format!(
+            "pattern captures {:?} from the sample message {:?}, expected {:?}",
+            params, sample, expected
+        )),
+        None => problems.push(format!("pattern does not match the sample message {:?}", sample)),
+    }
+    problems
+}
+
+fn list_placeholders(indices: &BTreeSet<usize>) -> String {
+    indices.iter().map(|i| format!("{{{}}}", i)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{CatalogFormat, CatalogLayer, ERRORS};
+    use crate::generate::parse_diagnostic_messages;
+
+    fn run(catalog: &Catalog, known: Option<&BTreeMap<u32, KnownMessage>>) -> (usize, String) {
+        let mut output = Vec::new();
+        let problems = check_catalog(catalog, known, &mut output).unwrap();
+        (problems, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_builtin_catalog_is_consistent() {
+        let known = parse_diagnostic_messages(include_str!("../catalog/diagnosticMessages.json")).unwrap();
+        let (problems, output) = run(&ERRORS, Some(&known));
+        assert_eq!(problems, 0, "{}", output);
+    }
+
+    #[test]
+    fn test_reports_unknown_placeholder_and_drift() {
+        let mut catalog = Catalog::default();
+        let layer = CatalogLayer::parse(
+            "[[error]]\ncode = 2339\npattern = \"Property '{0}' does not exist on type '{1}'.\"\nmessage = \"{2} is not on {1}\"\n\n\
+             [[error]]\ncode = 1155\npattern = \"Something old.\"\nmessage = \"Old\"\n",
+            CatalogFormat::Toml,
+            "test",
+        )
+        .unwrap();
+        catalog.apply(&layer).unwrap();
+        let known = parse_diagnostic_messages(
+            r#"{"'{0}' declarations must be initialized.": {"category": "Error", "code": 1155},
+                "Something old.": {"category": "Error", "code": 1355}}"#,
+        )
+        .unwrap();
+
+        let (problems, output) = run(&catalog, Some(&known));
+        assert_eq!(problems, 2);
+        assert!(output.contains("TS2339: message uses {2}, but the pattern only captures {0}, {1}"));
+        assert!(output.contains("TS1155: pattern differs from TypeScript's message"));
+        assert!(output.contains("(the catalog's pattern is TS1355's message)"));
+        assert!(output.contains("note: not in diagnosticMessages.json: TS2339"));
+    }
+
+    #[test]
+    fn test_reports_ambiguous_pattern() {
+        let mut catalog = Catalog::default();
+        let layer = CatalogLayer::parse(
+            "[[error]]\ncode = 1\npattern = \"{0}{1}\"\nmessage = \"{0} and {1}\"\n",
+            CatalogFormat::Toml,
+            "test",
+        )
+        .unwrap();
+        catalog.apply(&layer).unwrap();
+        let (problems, output) = run(&catalog, None);
+        assert_eq!(problems, 1);
+        assert!(output.contains("from the sample message"));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::errors::Catalog;
+use crate::translator::{explain_message, translate_message, Style};
+use crate::TranslationMode;
+use serde_json::{json, Value};
+
+/// The proxy-owned command behind the "Explain" code action.
+pub const EXPLAIN_COMMAND: &str = "ts-error-translator.explain";
+
+/// The empty `CodeActionKind`: the action documents an error rather than fixing
+/// it, so it must not be picked up by "fix all" or applied as a preferred fix.
+const EXPLAIN_KIND: &str = "";
+
+/// Builds an "Explain TSxxxx" code action for each diagnostic whose code is in the catalog.
+///
+/// `diagnostics` are those the editor sent, `originals` the server's versions
+/// (same order) whose messages the explanation is built from.
+pub fn explain_actions(
+    catalog: &Catalog,
+    diagnostics: &[Value],
+    originals: &[Value],
+    only: Option<&[Value]>,
+) -> Vec<Value> {
+    if !kind_requested(only) {
+        return Vec::new();
+    }
+
+    diagnostics
+        .iter()
+        .zip(originals)
+        .filter_map(|(diagnostic, original)| {
+            let code = original.get("code").and_then(Value::as_u64)? as u32;
+            catalog.get(code)?;
+            let message = original.get("message").and_then(Value::as_str)?;
+            let title = format!("Explain TS{}", code);
+            Some(json!({
+                "title": title,
+                "kind": EXPLAIN_KIND,
+                "diagnostics": [diagnostic],
+                "command": {
+                    "title": title,
+                    "command": EXPLAIN_COMMAND,
+                    "arguments": [{"code": code, "message": message}]
+                }
+            }))
+        })
+        .collect()
+}
+
+/// Whether `context.only` (if given) admits actions of the empty kind.
+fn kind_requested(only: Option<&[Value]>) -> bool {
+    let Some(only) = only else {
+        return true;
+    };
+    only.iter().filter_map(Value::as_str).any(|kind| kind == EXPLAIN_KIND)
+}
+
+/// The markdown shown for an explain command: the translation in `style` and,
+/// when the catalog has one, the long explanation.
+pub fn explanation_markdown(catalog: &Catalog, arguments: &Value, style: &Style) -> Option<(u32, String)> {
+    let args = arguments.get(0)?;
+    let code = args.get("code").and_then(Value::as_u64)? as u32;
+    let message = args.get("message").and_then(Value::as_str)?;
+    catalog.get(code)?;
+
+    let translation = translate_message(
+        catalog,
+        message,
+        Some(code as i64),
+        TranslationMode::Replace,
+        style,
+    );
+    let mut markdown = format!("# TS{}\n\n{}\n\n> {}", code, translation, message.replace('\n', "\n> "));
+    if let Some(body) = explain_message(catalog, message, code) {
+        markdown.push_str("\n\n");
+        markdown.push_str(&body);
+    }
+    Some((code, markdown))
+}
+
+/// Adds the explain command to `executeCommandProvider` in an `initialize` result.
+pub fn advertise_command(result: &mut Value) -> bool {
+    let Some(capabilities) = result.get_mut("capabilities").and_then(Value::as_object_mut) else {
+        return false;
+    };
+
+    let provider = capabilities
+        .entry("executeCommandProvider")
+        .or_insert_with(|| json!({"commands": []}));
+    let Some(provider) = provider.as_object_mut() else {
+        return false;
+    };
+    let commands = provider.entry("commands").or_insert_with(|| json!([]));
+    let Some(commands) = commands.as_array_mut() else {
+        return false;
+    };
+    if !commands.iter().any(|c| c == EXPLAIN_COMMAND) {
+        commands.push(Value::String(EXPLAIN_COMMAND.to_string()));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ERRORS;
+
+    fn diagnostic(code: u32, message: &str) -> Value {
+        json!({"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}}, "code": code, "message": message})
+    }
+
+    #[test]
+    fn test_actions_only_for_known_codes() {
+        let diagnostics = vec![
+            diagnostic(2304, "Cannot find name 'x'."),
+            diagnostic(99999, "Unknown"),
+        ];
+        let actions = explain_actions(&ERRORS, &diagnostics, &diagnostics, None);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0]["title"], "Explain TS2304");
+        assert_eq!(actions[0]["command"]["command"], EXPLAIN_COMMAND);
+    }
+
+    #[test]
+    fn test_actions_respect_only() {
+        let diagnostics = vec![diagnostic(2304, "Cannot find name 'x'.")];
+        let only = vec![json!("source.organizeImports")];
+        assert!(explain_actions(&ERRORS, &diagnostics, &diagnostics, Some(&only)).is_empty());
+        let only = vec![json!("quickfix")];
+        assert!(explain_actions(&ERRORS, &diagnostics, &diagnostics, Some(&only)).is_empty());
+        let only = vec![json!("")];
+        let actions = explain_actions(&ERRORS, &diagnostics, &diagnostics, Some(&only));
+        assert_eq!(actions[0]["kind"], "");
+    }
+
+    #[test]
+    fn test_explanation_includes_body() {
+        let args = json!([{"code": 2322, "message": "Type 'string' is not assignable to type 'number'."}]);
+        let (code, markdown) = explanation_markdown(&ERRORS, &args, &Style::default()).unwrap();
+        assert_eq!(code, 2322);
+        assert!(markdown.starts_with("# TS2322"));
+        assert!(markdown.contains("I was expecting a type matching 'number'"));
+        assert!(markdown.contains("```ts"));
+
+        // The configured style applies, as in diagnostics and hovers.
+        let style = Style {
+            bullet: "→".to_string(),
+            ..Style::default()
+        };
+        let (_, markdown) = explanation_markdown(&ERRORS, &args, &style).unwrap();
+        assert!(markdown.contains("→ I was expecting"));
+    }
+
+    #[test]
+    fn test_advertise_command() {
+        let mut result = json!({"capabilities": {"executeCommandProvider": {"commands": ["_typescript.organizeImports"]}}});
+        assert!(advertise_command(&mut result));
+        assert_eq!(
+            result["capabilities"]["executeCommandProvider"]["commands"],
+            json!(["_typescript.organizeImports", EXPLAIN_COMMAND])
+        );
+
+        let mut result = json!({"capabilities": {}});
+        advertise_command(&mut result);
+        assert_eq!(result["capabilities"]["executeCommandProvider"]["commands"], json!([EXPLAIN_COMMAND]));
+    }
+}
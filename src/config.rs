@@ -0,0 +1,331 @@
+use crate::jsonrpc::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::policy::{Policy, RuleSettings};
+use crate::translator::Style;
+use crate::TranslationMode;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Key of the proxy's own section in `initializationOptions` and workspace settings.
+pub const SETTINGS_KEY: &str = "tsErrorTranslator";
+
+/// Project config file, looked up from the workspace root upwards.
+pub const PROJECT_CONFIG_FILE: &str = ".ts-error-translator.toml";
+
+/// Settings as sent by the editor; anything left out keeps its current value.
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub enabled: Option<bool>,
+    pub mode: Option<TranslationMode>,
+    /// When set, only these codes are translated
+    #[serde(deserialize_with = "deserialize_codes")]
+    pub enabled_codes: Option<Vec<u32>>,
+    #[serde(deserialize_with = "deserialize_codes")]
+    pub disabled_codes: Option<Vec<u32>>,
+    pub catalogs: Option<Vec<PathBuf>>,
+    pub bullet: Option<String>,
+    /// Preset name or template for translated lines
+    pub template: Option<String>,
+    /// Documentation site name or URL template for `codeDescription.href`
+    pub docs_url: Option<String>,
+    pub types: Option<TypeSettings>,
+    pub rules: Option<Vec<RuleSettings>>,
+    /// TypeScript's output language, when it differs from the editor's
+    pub locale: Option<String>,
+    /// Keys this version does not know, reported rather than rejected
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+impl Settings {
+    /// The keys in `unknown`, with nested ones as `types.maxDeph` or `rules[0].code`.
+    pub fn unknown_keys(&self) -> Vec<String> {
+        let nested = self.types.iter().flat_map(|types| types.unknown.keys().map(|key| format!("types.{}", key)));
+        let rules = self.rules.iter().flatten().enumerate().flat_map(|(i, rule)| {
+            rule.unknown.keys().map(move |key| format!("rules[{}].{}", i, key))
+        });
+        self.unknown.keys().cloned().chain(nested).chain(rules).collect()
+    }
+}
+
+/// Limits for simplifying type text in translations.
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TypeSettings {
+    pub max_depth: Option<usize>,
+    pub max_union: Option<usize>,
+    pub max_properties: Option<usize>,
+    pub highlight_difference: Option<bool>,
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+/// The effective configuration of a proxy session.
+#[derive(Clone)]
+pub struct Config {
+    pub enabled: bool,
+    pub mode: TranslationMode,
+    /// Allow list; `None` allows every code not in `disabled_codes`
+    pub enabled_codes: Option<HashSet<u32>>,
+    pub disabled_codes: HashSet<u32>,
+    /// Extra catalogs, relative paths resolved against the workspace root
+    pub catalogs: Vec<PathBuf>,
+    pub style: Style,
+    pub policy: Policy,
+    /// TypeScript locale whose messages to match; `None` uses the editor's
+    pub locale: Option<String>,
+    /// Largest message read from the editor or the server, in bytes
+    pub max_message_size: usize,
+}
+
+impl Config {
+    pub fn new(mode: TranslationMode) -> Self {
+        Self {
+            enabled: true,
+            mode,
+            enabled_codes: None,
+            disabled_codes: HashSet::new(),
+            catalogs: Vec::new(),
+            style: Style::default(),
+            policy: Policy::default(),
+            locale: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    pub fn merge(&mut self, settings: Settings) {
+        if let Some(enabled) = settings.enabled {
+            self.enabled = enabled;
+        }
+        if let Some(mode) = settings.mode {
+            self.mode = mode;
+        }
+        if let Some(codes) = settings.enabled_codes {
+            self.enabled_codes = Some(codes.into_iter().collect());
+        }
+        if let Some(codes) = settings.disabled_codes {
+            self.disabled_codes = codes.into_iter().collect();
+        }
+        if let Some(catalogs) = settings.catalogs {
+            self.catalogs = catalogs;
+        }
+        if let Some(bullet) = settings.bullet {
+            self.style.bullet = bullet;
+        }
+        if let Some(template) = settings.template {
+            self.style.set_template(&template);
+        }
+        if let Some(docs_url) = settings.docs_url {
+            self.style.set_docs_url(&docs_url);
+        }
+        if let Some(types) = settings.types {
+            let limits = &mut self.style.types;
+            limits.max_depth = types.max_depth.unwrap_or(limits.max_depth);
+            limits.max_union = types.max_union.unwrap_or(limits.max_union);
+            limits.max_properties = types.max_properties.unwrap_or(limits.max_properties);
+            limits.highlight_difference = types.highlight_difference.unwrap_or(limits.highlight_difference);
+        }
+        if let Some(rules) = settings.rules {
+            self.policy = Policy::new(rules);
+        }
+        if let Some(locale) = settings.locale {
+            self.locale = Some(locale);
+        }
+    }
+}
+
+/// Removes the proxy's section from a settings object and parses it.
+///
+/// Returns `None` when there is no section; a malformed one is reported and ignored.
+pub fn take_settings(container: &mut Value) -> Option<Settings> {
+    let section = container.as_object_mut()?.remove(SETTINGS_KEY)?;
+    parse_settings(section)
+}
+
+pub fn parse_settings(section: Value) -> Option<Settings> {
+    if section.is_null() {
+        return None;
+    }
+    match serde_json::from_value(section) {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            eprintln!("ts-error-translator-proxy: ignoring invalid {} settings: {}", SETTINGS_KEY, e);
+            None
+        }
+    }
+}
+
+/// Finds the project settings for `root`: the nearest `.ts-error-translator.toml`
+/// or `package.json` with a `tsErrorTranslator` key, in `root` or above it.
+///
+/// Catalog paths are made relative to the directory the settings came from.
+/// A malformed file is reported and ignored.
+pub fn load_project_settings(root: &Path) -> Option<Settings> {
+    for dir in root.ancestors() {
+        let toml_path = dir.join(PROJECT_CONFIG_FILE);
+        let settings = if toml_path.is_file() {
+            read_project_toml(&toml_path)
+        } else {
+            match read_package_json(&dir.join("package.json")) {
+                Some(settings) => settings,
+                None => continue,
+            }
+        };
+        return settings.map(|mut settings| {
+            if let Some(catalogs) = &mut settings.catalogs {
+                for path in catalogs.iter_mut() {
+                    *path = dir.join(&*path);
+                }
+            }
+            settings
+        });
+    }
+    None
+}
+
+fn read_project_toml(path: &Path) -> Option<Settings> {
+    let result = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| toml::from_str(&source).map_err(|e| e.to_string()));
+    match result {
+        Ok(settings) => Some(settings),
+        Err(e) => {
+            eprintln!("ts-error-translator-proxy: ignoring {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// `None` when there is no `package.json` or it has no `tsErrorTranslator` key.
+fn read_package_json(path: &Path) -> Option<Option<Settings>> {
+    let source = std::fs::read_to_string(path).ok()?;
+    let mut package: Value = serde_json::from_str(&source).ok()?;
+    package.get(SETTINGS_KEY)?;
+    Some(take_settings(&mut package))
+}
+
+/// Accepts codes as numbers (`2322`) or strings (`"2322"`, `"TS2322"`).
+pub fn deserialize_codes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u32>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Code {
+        Number(u32),
+        Text(String),
+    }
+
+    let Some(codes) = Option::<Vec<Code>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    codes
+        .into_iter()
+        .map(|code| match code {
+            Code::Number(n) => Ok(n),
+            Code::Text(s) => {
+                let digits = s.strip_prefix("TS").or_else(|| s.strip_prefix("ts")).unwrap_or(&s);
+                digits
+                    .parse()
+                    .map_err(|_| serde::de::Error::custom(format!("invalid error code '{}'", s)))
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_take_settings_strips_section() {
+        let mut options = json!({
+            "tsErrorTranslator": {"mode": "append", "disabledCodes": [2322, "TS7006"], "bullet": "→"},
+            "preferences": {"importModuleSpecifier": "relative"}
+        });
+        let settings = take_settings(&mut options).unwrap();
+        assert_eq!(options, json!({"preferences": {"importModuleSpecifier": "relative"}}));
+
+        let mut config = Config::new(TranslationMode::Replace);
+        config.merge(settings);
+        assert!(matches!(config.mode, TranslationMode::Append));
+        assert!(config.disabled_codes.contains(&2322));
+        assert!(config.disabled_codes.contains(&7006));
+        assert_eq!(config.style.bullet, "→");
+    }
+
+    #[test]
+    fn test_merge_keeps_unset_fields() {
+        let mut config = Config::new(TranslationMode::Append);
+        config.merge(parse_settings(json!({"enabled": false, "types": {"maxUnion": 2}})).unwrap());
+        assert!(!config.enabled);
+        assert!(matches!(config.mode, TranslationMode::Append));
+        assert_eq!(config.style.types.max_union, 2);
+        assert_eq!(config.style.types.max_depth, 2);
+    }
+
+    fn temp_project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ts-error-translator-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("packages/app")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_project_toml_found_above_root() {
+        let dir = temp_project("toml");
+        std::fs::write(
+            dir.join(PROJECT_CONFIG_FILE),
+            "mode = \"append\"\nenabledCodes = [2322, \"TS2345\"]\ncatalogs = [\"errors.toml\"]\n",
+        )
+        .unwrap();
+        let settings = load_project_settings(&dir.join("packages/app"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let settings = settings.unwrap();
+        assert!(matches!(settings.mode, Some(TranslationMode::Append)));
+        assert_eq!(settings.enabled_codes, Some(vec![2322, 2345]));
+        assert_eq!(settings.catalogs, Some(vec![dir.join("errors.toml")]));
+    }
+
+    #[test]
+    fn test_project_package_json_key() {
+        let dir = temp_project("package");
+        std::fs::write(dir.join("package.json"), r#"{"name": "root", "tsErrorTranslator": {"disabledCodes": [7006]}}"#).unwrap();
+        // A package.json without the key does not stop the search.
+        std::fs::write(dir.join("packages/app/package.json"), r#"{"name": "app"}"#).unwrap();
+        let settings = load_project_settings(&dir.join("packages/app"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(settings.unwrap().disabled_codes, Some(vec![7006]));
+    }
+
+    #[test]
+    fn test_unknown_keys_collected_at_every_level() {
+        let settings = parse_settings(json!({
+            "mode": "append",
+            "disabledCode": [2322],
+            "types": {"maxDeph": 1, "maxUnion": 2},
+            "rules": [{"codes": ["TS2304"], "translate": false}, {"code": [2322], "severity": ["hint"], "mode": "related"}]
+        }))
+        .unwrap();
+        assert!(matches!(settings.mode, Some(TranslationMode::Append)));
+        assert_eq!(settings.types.as_ref().unwrap().max_union, Some(2));
+        let rules = settings.rules.as_ref().unwrap();
+        assert_eq!(rules[0].codes, Some(vec![2304]));
+        assert!(matches!(rules[1].mode, Some(TranslationMode::Related)));
+        assert_eq!(
+            settings.unknown_keys(),
+            vec!["disabledCode", "types.maxDeph", "rules[1].code", "rules[1].severity"]
+        );
+
+        let settings: Settings = toml::from_str("bulet = \"-\"\n[types]\nmaxDepth = 1\n").unwrap();
+        assert_eq!(settings.unknown_keys(), vec!["bulet"]);
+    }
+
+    #[test]
+    fn test_invalid_settings_ignored() {
+        let mut options = json!({"tsErrorTranslator": {"mode": "sideways"}});
+        assert!(take_settings(&mut options).is_none());
+    }
+}
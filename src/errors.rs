@@ -1,24 +1,401 @@
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::LazyLock;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
 
+/// The catalog shipped with the binary, see `catalog/errors.toml`.
+const BUILTIN_CATALOG: &str = include_str!("../catalog/errors.toml");
+
+/// TypeScript's message texts, generated by `generate-catalog`.
+const BUILTIN_PATTERNS: &str = include_str!("../catalog/patterns.toml");
+
+/// TypeScript's message texts in other languages by TS locale, generated by `generate-catalog --locale`.
+const BUILTIN_LOCALES: &[(&str, &str)] = &[
+    ("de", include_str!("../catalog/locales/de.toml")),
+    ("ja", include_str!("../catalog/locales/ja.toml")),
+];
+
+/// Stands in for a parameter a localized message leaves out.
+const MISSING_PARAM: &str = "…";
+
+/// A `{0}`, `{1}`, ... parameter in a pattern or message.
+pub static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{(\d+)\}").unwrap());
+
+#[derive(Clone)]
 pub struct ErrorInfo {
     /// Regex pattern to match the original error and extract parameters
     pub pattern: Regex,
+    /// The pattern as written in the catalog, with {0}, {1}, etc. placeholders
+    pub pattern_text: String,
     /// Human-readable message with {0}, {1}, etc. placeholders
-    pub message: &'static str,
+    pub message: String,
+    /// Optional long-form markdown explanation
+    pub body: Option<String>,
+    /// TypeScript's message in the catalog's locale, see [`Catalog::localize`]
+    pub localized: Option<LocalizedPattern>,
 }
 
 impl ErrorInfo {
-    fn new(pattern: &str, message: &'static str) -> Self {
-        Self {
-            pattern: pattern_to_regex(pattern),
+    /// Extracts the parameters of `message`, which may be in English or in the catalog's locale.
+    pub fn params<'a>(&self, message: &'a str) -> Option<Vec<&'a str>> {
+        extract_params(&self.pattern, message).or_else(|| {
+            let count = placeholders(&self.pattern_text).into_iter().max().map_or(0, |max| max + 1);
+            self.localized.as_ref()?.params(message, count)
+        })
+    }
+}
+
+/// A TypeScript message in another language, whose placeholders may come in a different order.
+#[derive(Clone)]
+pub struct LocalizedPattern {
+    pub text: String,
+    regex: Regex,
+    /// Placeholder index of each capture group
+    order: Vec<usize>,
+}
+
+impl LocalizedPattern {
+    fn new(text: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            text: text.to_string(),
+            regex: pattern_to_regex(text)?,
+            order: placeholders(text),
+        })
+    }
+
+    /// At least `count` parameters of `message` by placeholder index, `{0}`
+    /// first. Translations sometimes leave a parameter out; it reads as `…`.
+    fn params<'a>(&self, message: &'a str, count: usize) -> Option<Vec<&'a str>> {
+        let captured = extract_params(&self.regex, message)?;
+        let mut params = vec![None; self.order.iter().max().map_or(0, |max| max + 1).max(count)];
+        for (index, value) in self.order.iter().zip(captured) {
+            params[*index].get_or_insert(value);
+        }
+        Some(params.into_iter().map(|param| param.unwrap_or(MISSING_PARAM)).collect())
+    }
+}
+
+/// A set of translations keyed by TypeScript error code.
+#[derive(Clone, Default)]
+pub struct Catalog {
+    entries: HashMap<u32, ErrorInfo>,
+    /// TypeScript's own message for each code, used as the pattern of entries that give none
+    known_patterns: Arc<HashMap<u32, String>>,
+}
+
+/// The contents of `catalog/patterns.toml`: every message in TypeScript's
+/// `diagnosticMessages.json`, ordered by code.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct PatternSet {
+    #[serde(default, rename = "message")]
+    pub messages: Vec<KnownMessage>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KnownMessage {
+    pub code: u32,
+    /// Left out of locale pattern sets
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub category: String,
+    pub pattern: String,
+}
+
+/// A parsed catalog file, applied on top of a [`Catalog`].
+///
+/// Entries may leave out fields to inherit them from the layers below, or set
+/// `disabled = true` to remove a translation entirely.
+#[derive(Clone, Deserialize)]
+pub struct CatalogLayer {
+    #[serde(skip)]
+    origin: String,
+    #[serde(default, rename = "error", alias = "errors")]
+    errors: Vec<CatalogEntry>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogEntry {
+    code: u32,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CatalogFormat {
+    Toml,
+    Json,
+}
+
+impl CatalogFormat {
+    /// Picks the format from a file extension, defaulting to TOML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => CatalogFormat::Json,
+            _ => CatalogFormat::Toml,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        origin: String,
+        message: String,
+    },
+    Pattern {
+        origin: String,
+        code: u32,
+        pattern: String,
+        source: regex::Error,
+    },
+    Incomplete {
+        origin: String,
+        code: u32,
+    },
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::Io { path, source } => {
+                write!(f, "cannot read catalog {}: {}", path.display(), source)
+            }
+            CatalogError::Parse { origin, message } => {
+                write!(f, "invalid catalog {}: {}", origin, message)
+            }
+            CatalogError::Pattern {
+                origin,
+                code,
+                pattern,
+                source,
+            } => write!(
+                f,
+                "invalid pattern for TS{} in {}: {}\n  pattern: {}",
+                code, origin, source, pattern
+            ),
+            CatalogError::Incomplete { origin, code } => write!(
+                f,
+                "TS{} in {} is not defined by an earlier catalog, so it needs both a pattern and a message",
+                code, origin
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+impl CatalogLayer {
+    pub fn parse(source: &str, format: CatalogFormat, origin: &str) -> Result<Self, CatalogError> {
+        let mut layer: CatalogLayer = match format {
+            CatalogFormat::Toml => toml::from_str(source).map_err(|e| e.to_string()),
+            CatalogFormat::Json => serde_json::from_str(source).map_err(|e| e.to_string()),
+        }
+        .map_err(|message| CatalogError::Parse {
+            origin: origin.to_string(),
             message,
+        })?;
+        layer.origin = origin.to_string();
+        Ok(layer)
+    }
+
+    /// Code and pattern (if given) of every entry that provides a message.
+    pub fn translations(&self) -> impl Iterator<Item = (u32, Option<&str>)> {
+        self.errors
+            .iter()
+            .filter(|entry| !entry.disabled && entry.message.is_some())
+            .map(|entry| (entry.code, entry.pattern.as_deref()))
+    }
+
+    /// Loads a catalog file, choosing TOML or JSON from its extension.
+    pub fn load(path: &Path) -> Result<Self, CatalogError> {
+        let source = std::fs::read_to_string(path).map_err(|source| CatalogError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&source, CatalogFormat::from_path(path), &path.display().to_string())
+    }
+}
+
+impl Catalog {
+    /// The catalog embedded in the binary.
+    pub fn builtin() -> Self {
+        let patterns: PatternSet = toml::from_str(BUILTIN_PATTERNS).expect("built-in patterns are valid");
+        let layer = CatalogLayer::parse(BUILTIN_CATALOG, CatalogFormat::Toml, "built-in catalog")
+            .expect("built-in catalog is valid");
+        let mut catalog = Self {
+            entries: HashMap::new(),
+            known_patterns: Arc::new(
+                patterns
+                    .messages
+                    .into_iter()
+                    .map(|message| (message.code, message.pattern))
+                    .collect(),
+            ),
+        };
+        catalog.apply(&layer).expect("built-in catalog is valid");
+        catalog
+    }
+
+    /// Applies `layer` on top of this catalog, overriding, adding or disabling entries.
+    pub fn apply(&mut self, layer: &CatalogLayer) -> Result<(), CatalogError> {
+        for entry in &layer.errors {
+            if entry.disabled {
+                self.entries.remove(&entry.code);
+                continue;
+            }
+
+            // New entries may leave the pattern to TypeScript's own message.
+            let pattern_text = match &entry.pattern {
+                Some(pattern) => Some(pattern.clone()),
+                None if !self.entries.contains_key(&entry.code) => self.known_patterns.get(&entry.code).cloned(),
+                None => None,
+            };
+            let pattern = pattern_text
+                .as_deref()
+                .map(|p| {
+                    pattern_to_regex(p).map_err(|source| CatalogError::Pattern {
+                        origin: layer.origin.clone(),
+                        code: entry.code,
+                        pattern: p.to_string(),
+                        source,
+                    })
+                })
+                .transpose()?;
+
+            match self.entries.get_mut(&entry.code) {
+                Some(info) => {
+                    if let (Some(pattern), Some(text)) = (pattern, pattern_text) {
+                        info.pattern = pattern;
+                        info.pattern_text = text;
+                    }
+                    if let Some(message) = &entry.message {
+                        info.message = message.clone();
+                    }
+                    if entry.body.is_some() {
+                        info.body = entry.body.clone();
+                    }
+                }
+                None => {
+                    let (Some(pattern), Some(pattern_text), Some(message)) = (pattern, pattern_text, &entry.message)
+                    else {
+                        return Err(CatalogError::Incomplete {
+                            origin: layer.origin.clone(),
+                            code: entry.code,
+                        });
+                    };
+                    self.entries.insert(
+                        entry.code,
+                        ErrorInfo {
+                            pattern,
+                            pattern_text,
+                            message: message.clone(),
+                            body: entry.body.clone(),
+                            localized: None,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the translation for `code`, as a `disabled = true` entry would.
+    pub fn disable(&mut self, code: u32) {
+        self.entries.remove(&code);
+    }
+
+    /// Keeps only the translations whose code satisfies `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        self.entries.retain(|code, _| keep(*code));
+    }
+
+    /// Lets entries also match TypeScript's messages in `locale` (e.g. `de`, `ja`, `pt-br`).
+    ///
+    /// Returns `false` if there is no pattern set for `locale`; English needs none.
+    pub fn localize(&mut self, locale: &str) -> bool {
+        let patterns = locale_patterns(locale);
+        for (code, info) in self.entries.iter_mut() {
+            info.localized = patterns.and_then(|patterns| patterns.get(code)).cloned();
         }
+        patterns.is_some() || locale_language(locale) == "en"
+    }
+
+    pub fn get(&self, code: u32) -> Option<&ErrorInfo> {
+        self.entries.get(&code)
     }
+
+    /// All entries, ordered by code.
+    pub fn entries(&self) -> Vec<(u32, &ErrorInfo)> {
+        let mut entries: Vec<_> = self.entries.iter().map(|(code, info)| (*code, info)).collect();
+        entries.sort_unstable_by_key(|(code, _)| *code);
+        entries
+    }
+
+    /// Finds the entry whose pattern matches `message` when no code is known.
+    ///
+    /// If several patterns match, the most specific one (capturing the least
+    /// text) wins, with ties broken by the lower code.
+    pub fn find_match<'a>(&self, message: &'a str) -> Option<(u32, &ErrorInfo, Vec<&'a str>)> {
+        self.entries
+            .iter()
+            .filter_map(|(&code, info)| info.params(message).map(|params| (code, info, params)))
+            .min_by_key(|(code, _, params)| (params.iter().map(|p| p.len()).sum::<usize>(), *code))
+    }
+}
+
+pub static ERRORS: LazyLock<Catalog> = LazyLock::new(Catalog::builtin);
+
+static LOCALE_PATTERNS: LazyLock<HashMap<&str, HashMap<u32, LocalizedPattern>>> = LazyLock::new(|| {
+    BUILTIN_LOCALES
+        .iter()
+        .map(|(locale, source)| {
+            let set: PatternSet = toml::from_str(source).expect("built-in locale patterns are valid");
+            let patterns = set
+                .messages
+                .iter()
+                .map(|message| {
+                    let pattern = LocalizedPattern::new(&message.pattern).expect("built-in locale patterns are valid");
+                    (message.code, pattern)
+                })
+                .collect();
+            (*locale, patterns)
+        })
+        .collect()
+});
+
+/// The built-in pattern set for `locale`, falling back from a regional locale to its language.
+fn locale_patterns(locale: &str) -> Option<&'static HashMap<u32, LocalizedPattern>> {
+    let locale = locale.to_ascii_lowercase().replace('_', "-");
+    LOCALE_PATTERNS
+        .get(locale.as_str())
+        .or_else(|| LOCALE_PATTERNS.get(locale_language(&locale).as_str()))
+}
+
+fn locale_language(locale: &str) -> String {
+    locale.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase()
+}
+
+/// Placeholder indices of `text` in order of appearance.
+pub fn placeholders(text: &str) -> Vec<usize> {
+    PLACEHOLDER
+        .captures_iter(text)
+        .filter_map(|caps| caps[1].parse().ok())
+        .collect()
 }
 
-fn pattern_to_regex(pattern: &str) -> Regex {
+fn pattern_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
     let mut regex_str = String::with_capacity(pattern.len() * 2);
     regex_str.push('^');
 
@@ -28,13 +405,15 @@ fn pattern_to_regex(pattern: &str) -> Regex {
     let bytes = pattern.as_bytes();
     while i < bytes.len() {
         if bytes[i] == b'{' {
-            regex_str.push_str(&regex::escape(&pattern[last_end..i]));
-
-            while i < bytes.len() && bytes[i] != b'}' {
-                i += 1;
+            // Only `{0}`, `{1}`, ... are parameters; other braces (e.g. `export {}`) are literal.
+            let digits = bytes[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+            let close = i + 1 + digits;
+            if digits > 0 && bytes.get(close) == Some(&b'}') {
+                regex_str.push_str(&regex::escape(&pattern[last_end..i]));
+                regex_str.push_str("(.+?)");
+                last_end = close + 1;
+                i = close;
             }
-            regex_str.push_str("(.+?)");
-            last_end = i + 1;
         }
         i += 1;
     }
@@ -44,7 +423,7 @@ fn pattern_to_regex(pattern: &str) -> Regex {
     }
 
     regex_str.push('$');
-    Regex::new(&regex_str).unwrap()
+    Regex::new(&regex_str)
 }
 
 pub fn extract_params<'a>(pattern: &Regex, message: &'a str) -> Option<Vec<&'a str>> {
@@ -65,308 +444,6 @@ pub fn substitute_params(template: &str, params: &[&str]) -> String {
     result
 }
 
-pub static ERRORS: LazyLock<HashMap<u32, ErrorInfo>> = LazyLock::new(|| {
-    let mut m = HashMap::new();
-
-    // 1000-series: Syntax and parsing errors
-    m.insert(1002, ErrorInfo::new(
-        "Unterminated string literal.",
-        "You've started a string but haven't ended it.",
-    ));
-    m.insert(1003, ErrorInfo::new(
-        "Identifier expected.",
-        "I was expecting a name but none was provided.",
-    ));
-    m.insert(1005, ErrorInfo::new(
-        "'{0}' expected.",
-        "'{0}' is expected here.",
-    ));
-    m.insert(1006, ErrorInfo::new(
-        "A file cannot end inside a template literal.",
-        "A file cannot end inside a template literal.",
-    ));
-    m.insert(1009, ErrorInfo::new(
-        "Trailing comma not allowed.",
-        "You've added a trailing comma when you're not supposed to.",
-    ));
-    m.insert(1014, ErrorInfo::new(
-        "A rest parameter must be last in a parameter list.",
-        "A parameter that starts with `...` must be the last one in the list.",
-    ));
-    m.insert(1015, ErrorInfo::new(
-        "Parameter cannot have question mark and initializer.",
-        "A parameter cannot use both a question mark and a default value - choose one or the other.",
-    ));
-    m.insert(1091, ErrorInfo::new(
-        "Only a single variable declaration is allowed in a 'for...in' statement.",
-        "You can only create a single variable in a 'for...in' statement.",
-    ));
-    m.insert(1109, ErrorInfo::new(
-        "Expression expected.",
-        "I was expecting some code that gives me a value.",
-    ));
-    m.insert(1117, ErrorInfo::new(
-        "An object literal cannot have multiple properties with the same name.",
-        "You can't add the same property multiple times to an object.",
-    ));
-    m.insert(1155, ErrorInfo::new(
-        "A 'const' assertions can only be applied to references to enum members, or string, number, boolean, array, or object literals.",
-        "A `const` must be given a value when you declare it.",
-    ));
-    m.insert(1163, ErrorInfo::new(
-        "A computed property name must be of type '{0}'.",
-        "A computed property name must be of type '{0}'.",
-    ));
-    m.insert(1208, ErrorInfo::new(
-        "'{0}' cannot be compiled under '--isolatedModules' because it is considered a global script file. Add an import, export, or an empty 'export {}' statement to make it a module.",
-        "'{0}' is being treated as a script, not a module. Add an import, export, or an empty 'export {{}}' statement to make it a module.",
-    ));
-    m.insert(1240, ErrorInfo::new(
-        "Unable to resolve signature of class decorator when called as an expression.",
-        "I can't resolve the signature of this class decorator.",
-    ));
-    m.insert(1254, ErrorInfo::new(
-        "A 'const' assertion can only be applied to a string, number, boolean, array, or object literal.",
-        "A 'const' assertion can only be applied to a string, number, boolean, array, or object literal.",
-    ));
-    m.insert(1268, ErrorInfo::new(
-        "'await' expressions are only allowed at the top level of a file when that file is a module, but this file has no imports or exports. Consider adding an empty 'export {}' to make this file a module.",
-        "'await' expressions are only allowed at the top level of a file when that file is a module. Add an empty 'export {{}}' to make this file a module.",
-    ));
-    m.insert(1313, ErrorInfo::new(
-        "A class may only extend another class.",
-        "A class may only extend another class.",
-    ));
-    m.insert(1434, ErrorInfo::new(
-        "Top-level 'await' expressions are only allowed when the 'module' option is set to 'es2022', 'esnext', 'system', 'node16', 'nodenext', or 'preserve', and the 'target' option is set to 'es2017' or higher.",
-        "You need to enable top-level await in your tsconfig.",
-    ));
-
-    // 2300-series: Name resolution and declarations
-    m.insert(2304, ErrorInfo::new(
-        "Cannot find name '{0}'.",
-        "I can't find '{0}' - it might not be imported or defined.",
-    ));
-    m.insert(2305, ErrorInfo::new(
-        "Module '{0}' has no exported member '{1}'.",
-        "'{1}' is not exported from '{0}'.",
-    ));
-    m.insert(2307, ErrorInfo::new(
-        "Cannot find module '{0}' or its corresponding type declarations.",
-        "This could be one of two things - either '{0}' doesn't exist on your file system, or I can't find any type declarations for it.",
-    ));
-    m.insert(2312, ErrorInfo::new(
-        "An interface can only extend an object type or intersection of object types with statically known members.",
-        "An interface can only extend an object type or another interface.",
-    ));
-    m.insert(2314, ErrorInfo::new(
-        "Generic type '{0}' requires {1} type argument(s).",
-        "'{0}' requires {1} type argument(s) - you need to pass them via a generic.",
-    ));
-    m.insert(2322, ErrorInfo::new(
-        "Type '{0}' is not assignable to type '{1}'.",
-        "I was expecting a type matching '{1}' but instead you passed '{0}'.",
-    ));
-    m.insert(2324, ErrorInfo::new(
-        "Property '{0}' is missing in type '{1}'.",
-        "Property '{0}' is missing in type '{1}'.",
-    ));
-    m.insert(2326, ErrorInfo::new(
-        "Types of property '{0}' are incompatible.",
-        "Types of property '{0}' are incompatible.",
-    ));
-    m.insert(2327, ErrorInfo::new(
-        "Index signature is missing in type '{0}'.",
-        "Index signature is missing in type '{0}'.",
-    ));
-    m.insert(2339, ErrorInfo::new(
-        "Property '{0}' does not exist on type '{1}'.",
-        "You're trying to access '{0}' on an object that doesn't contain it.",
-    ));
-    m.insert(2344, ErrorInfo::new(
-        "Type '{0}' does not satisfy the constraint '{1}'.",
-        "Type '{0}' doesn't satisfy the constraint '{1}'.",
-    ));
-    m.insert(2345, ErrorInfo::new(
-        "Argument of type '{0}' is not assignable to parameter of type '{1}'.",
-        "I was expecting '{1}' but you passed '{0}'.",
-    ));
-    m.insert(2349, ErrorInfo::new(
-        "This expression is not callable.",
-        "You're trying to call something that isn't a function.",
-    ));
-    m.insert(2352, ErrorInfo::new(
-        "Conversion of type '{0}' to type '{1}' may be a mistake because neither type sufficiently overlaps with the other.",
-        "Converting '{0}' to '{1}' may be a mistake - these types don't overlap.",
-    ));
-    m.insert(2353, ErrorInfo::new(
-        "Object literal may only specify known properties, and '{0}' does not exist in type '{1}'.",
-        "You can't pass property '{0}' to type '{1}'.",
-    ));
-    m.insert(2355, ErrorInfo::new(
-        "A function whose declared type is neither 'void' nor 'any' must return a value.",
-        "This function says it returns something, but it doesn't return anything.",
-    ));
-    m.insert(2365, ErrorInfo::new(
-        "Operator '{0}' cannot be applied to types '{1}' and '{2}'.",
-        "Operator '{0}' cannot be applied to types '{1}' and '{2}'.",
-    ));
-    m.insert(2393, ErrorInfo::new(
-        "Duplicate function implementation.",
-        "You've got a duplicate function implementation.",
-    ));
-    m.insert(2414, ErrorInfo::new(
-        "Class name cannot be '{0}'.",
-        "Class name cannot be '{0}'.",
-    ));
-    m.insert(2451, ErrorInfo::new(
-        "Cannot redeclare block-scoped variable '{0}'.",
-        "'{0}' has already been declared - you can't declare it again.",
-    ));
-    m.insert(2488, ErrorInfo::new(
-        "Type '{0}' must have a '[Symbol.iterator]()' method that returns an iterator.",
-        "Type '{0}' must have a '[Symbol.iterator]()' method to use for-of.",
-    ));
-    m.insert(2551, ErrorInfo::new(
-        "Property '{0}' does not exist on type '{1}'. Did you mean '{2}'?",
-        "You're trying to access '{0}' on an object that doesn't contain it. Did you mean '{2}'?",
-    ));
-    m.insert(2552, ErrorInfo::new(
-        "Cannot find name '{0}'. Did you mean '{1}'?",
-        "Cannot find name '{0}'. Did you mean '{1}'?",
-    ));
-    m.insert(2554, ErrorInfo::new(
-        "Expected {0} arguments, but got {1}.",
-        "This function needs {0} argument(s), but you're passing {1}.",
-    ));
-    m.insert(2556, ErrorInfo::new(
-        "A spread argument must either have a tuple type or be passed to a rest parameter.",
-        "A spread argument must be from a tuple or passed to a rest parameter.",
-    ));
-    m.insert(2571, ErrorInfo::new(
-        "Object is of type 'unknown'.",
-        "I don't know what type this object is, so I've defaulted it to 'unknown'.",
-    ));
-    m.insert(2590, ErrorInfo::new(
-        "Expression produces a union type that is too complex to represent.",
-        "This expression produces a type that's too complex for me to represent.",
-    ));
-    m.insert(2604, ErrorInfo::new(
-        "JSX element type '{0}' does not have any construct or call signatures.",
-        "JSX element type '{0}' doesn't have any construct or call signatures.",
-    ));
-    m.insert(2614, ErrorInfo::new(
-        "Module '{0}' has no default export.",
-        "Module '{0}' has no default export.",
-    ));
-    m.insert(2686, ErrorInfo::new(
-        "'{0}' refers to a UMD global, but the current file is a module. Consider adding an import instead.",
-        "'{0}' refers to a UMD global, but this file is a module. Consider adding an import instead.",
-    ));
-    m.insert(2722, ErrorInfo::new(
-        "Cannot invoke an object which is possibly 'undefined'.",
-        "This value might be undefined - check that it exists before using it.",
-    ));
-    m.insert(2739, ErrorInfo::new(
-        "Type '{0}' is missing the following properties from type '{1}': {2}",
-        "'{0}' is missing some required properties from type '{1}': {2}",
-    ));
-    m.insert(2741, ErrorInfo::new(
-        "Property '{0}' is missing in type '{1}' but required in type '{2}'.",
-        "You haven't passed all the required properties to '{2}' - '{1}' is missing the '{0}' property.",
-    ));
-    m.insert(2749, ErrorInfo::new(
-        "'{0}' refers to a value, but is being used as a type here. Did you mean 'typeof {0}'?",
-        "'{0}' is a value, not a type. Did you mean 'typeof {0}'?",
-    ));
-    m.insert(2761, ErrorInfo::new(
-        "Type import '{0}' cannot be used as a value because it was exported using 'export type'.",
-        "'{0}' is a type-only import and can't be used as a value.",
-    ));
-    m.insert(2775, ErrorInfo::new(
-        "Assertions require every name in the call target to be declared with an explicit type annotation.",
-        "Assertions require every name to be declared with an explicit type annotation.",
-    ));
-    m.insert(2783, ErrorInfo::new(
-        "'{0}' is specified more than once, so this usage will be overwritten.",
-        "'{0}' is specified more than once - the later value will overwrite earlier ones.",
-    ));
-
-    // 5000-series
-    m.insert(5075, ErrorInfo::new(
-        "'{0}' is a type and cannot be imported in JavaScript files. Use '{1}' in a JSDoc type annotation.",
-        "'{0}' is a type and can't be imported in JavaScript files.",
-    ));
-
-    // 6000-series
-    m.insert(6133, ErrorInfo::new(
-        "'{0}' is declared but its value is never read.",
-        "'{0}' is declared but never used.",
-    ));
-    m.insert(6142, ErrorInfo::new(
-        "Module '{0}' was resolved to '{1}', but '--resolveJsonModule' is not used.",
-        "Module '{0}' is imported but '--resolveJsonModule' is not enabled in your tsconfig.",
-    ));
-    m.insert(6196, ErrorInfo::new(
-        "'{0}' is declared but never used.",
-        "'{0}' is declared but never used.",
-    ));
-    m.insert(6244, ErrorInfo::new(
-        "Module '{0}' was resolved to '{1}', but '--jsx' is not set.",
-        "Module '{0}' was resolved but '--jsx' is not set in your tsconfig.",
-    ));
-
-    // 7000-series: Strict mode errors
-    m.insert(7006, ErrorInfo::new(
-        "Parameter '{0}' implicitly has an '{1}' type.",
-        "I don't know what type '{0}' is supposed to be, so I've defaulted it to '{1}'. Your tsconfig says I should throw an error here.",
-    ));
-    m.insert(7017, ErrorInfo::new(
-        "Element implicitly has an 'any' type because type '{0}' has no index signature.",
-        "Type '{0}' has no index signature, so element access gives an implicit 'any' type.",
-    ));
-    m.insert(7026, ErrorInfo::new(
-        "JSX element implicitly has type 'any' because no interface 'JSX.IntrinsicElements' exists.",
-        "JSX element has an implicit 'any' type because 'JSX.IntrinsicElements' doesn't exist.",
-    ));
-    m.insert(7053, ErrorInfo::new(
-        "Element implicitly has an 'any' type because expression of type '{0}' can't be used to index type '{1}'.",
-        "Expression of type '{0}' can't be used to index type '{1}'.",
-    ));
-    m.insert(7057, ErrorInfo::new(
-        "'yield' expression implicitly results in an 'any' type because its containing generator lacks a return-type annotation.",
-        "'yield' needs a type annotation on the containing generator.",
-    ));
-    m.insert(7061, ErrorInfo::new(
-        "A mapped type may not declare properties or methods.",
-        "A mapped type may not declare properties or methods.",
-    ));
-
-    // 8000-series
-    m.insert(8016, ErrorInfo::new(
-        "Type arguments can only be used in TypeScript files.",
-        "Type arguments can only be used in TypeScript files.",
-    ));
-
-    // 17000-series
-    m.insert(17004, ErrorInfo::new(
-        "Cannot use JSX unless the '--jsx' flag is provided.",
-        "Add 'jsx' to your tsconfig.json to use JSX.",
-    ));
-    m.insert(18004, ErrorInfo::new(
-        "No value exists in scope for the shorthand property '{0}'. Either declare one or provide an initializer.",
-        "No value exists for shorthand property '{0}'. Either declare one or provide an initializer.",
-    ));
-
-    // 95000-series
-    m.insert(95050, ErrorInfo::new(
-        "Convert function to an async function",
-        "Consider converting this function to an async function.",
-    ));
-
-    m
-});
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -374,7 +451,7 @@ mod tests {
     #[test]
     fn test_pattern_to_regex_simple() {
         let pattern = "Property '{0}' does not exist on type '{1}'.";
-        let regex = pattern_to_regex(pattern);
+        let regex = pattern_to_regex(pattern).unwrap();
         let caps = regex.captures("Property 'foo' does not exist on type 'Bar'.").unwrap();
         assert_eq!(caps.get(1).unwrap().as_str(), "foo");
         assert_eq!(caps.get(2).unwrap().as_str(), "Bar");
@@ -383,7 +460,7 @@ mod tests {
     #[test]
     fn test_pattern_to_regex_three_params() {
         let pattern = "Expected {0} arguments, but got {1}.";
-        let regex = pattern_to_regex(pattern);
+        let regex = pattern_to_regex(pattern).unwrap();
         let caps = regex.captures("Expected 2 arguments, but got 3.").unwrap();
         assert_eq!(caps.get(1).unwrap().as_str(), "2");
         assert_eq!(caps.get(2).unwrap().as_str(), "3");
@@ -399,8 +476,143 @@ mod tests {
 
     #[test]
     fn test_extract_params() {
-        let info = ERRORS.get(&2339).unwrap();
+        let info = ERRORS.get(2339).unwrap();
         let params = extract_params(&info.pattern, "Property 'foo' does not exist on type 'Bar'.").unwrap();
         assert_eq!(params, vec!["foo", "Bar"]);
     }
+
+    #[test]
+    fn test_builtin_catalog_loads() {
+        assert!(ERRORS.get(2322).is_some());
+        assert!(ERRORS.get(99999).is_none());
+    }
+
+    fn layer(source: &str) -> CatalogLayer {
+        CatalogLayer::parse(source, CatalogFormat::Toml, "test").unwrap()
+    }
+
+    #[test]
+    fn test_parse_json_catalog() {
+        let source = r#"{"errors": [{"code": 2322, "pattern": "Type '{0}' is not assignable to type '{1}'.", "message": "Nope: {0} vs {1}", "body": "Long form"}]}"#;
+        let mut catalog = Catalog::default();
+        catalog
+            .apply(&CatalogLayer::parse(source, CatalogFormat::Json, "test").unwrap())
+            .unwrap();
+        let info = catalog.get(2322).unwrap();
+        assert_eq!(info.message, "Nope: {0} vs {1}");
+        assert_eq!(info.body.as_deref(), Some("Long form"));
+    }
+
+    #[test]
+    fn test_layer_overrides_message_only() {
+        let mut catalog = ERRORS.clone();
+        catalog
+            .apply(&layer("[[error]]\ncode = 2339\nmessage = \"No '{0}' here.\"\n"))
+            .unwrap();
+        let info = catalog.get(2339).unwrap();
+        assert_eq!(info.message, "No '{0}' here.");
+        let params = extract_params(&info.pattern, "Property 'foo' does not exist on type 'Bar'.").unwrap();
+        assert_eq!(params, vec!["foo", "Bar"]);
+    }
+
+    #[test]
+    fn test_layer_disables_entry() {
+        let mut catalog = ERRORS.clone();
+        catalog.apply(&layer("[[error]]\ncode = 2322\ndisabled = true\n")).unwrap();
+        assert!(catalog.get(2322).is_none());
+        assert!(catalog.get(2339).is_some());
+    }
+
+    #[test]
+    fn test_layer_new_entry_requires_pattern() {
+        let mut catalog = Catalog::default();
+        let err = catalog
+            .apply(&layer("[[error]]\ncode = 4242\nmessage = \"x\"\n"))
+            .err()
+            .unwrap();
+        assert!(matches!(err, CatalogError::Incomplete { code: 4242, .. }));
+    }
+
+    #[test]
+    fn test_new_entry_uses_known_pattern() {
+        let mut catalog = Catalog::builtin();
+        assert!(catalog.get(2300).is_none());
+        let layer = CatalogLayer::parse("[[error]]\ncode = 2300\nmessage = \"'{0}' is defined twice.\"\n", CatalogFormat::Toml, "test").unwrap();
+        catalog.apply(&layer).unwrap();
+
+        let info = catalog.get(2300).unwrap();
+        assert_eq!(info.pattern_text, "Duplicate identifier '{0}'.");
+        assert_eq!(extract_params(&info.pattern, "Duplicate identifier 'x'.").unwrap(), vec!["x"]);
+    }
+
+    #[test]
+    fn test_pattern_literal_braces() {
+        let regex = pattern_to_regex("Add an empty 'export {}' statement to '{0}'.").unwrap();
+        let caps = regex.captures("Add an empty 'export {}' statement to 'a.ts'.").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "a.ts");
+    }
+
+    #[test]
+    fn test_parse_error_names_origin() {
+        let source = "[[error]]\ncode = \"1005\"\n";
+        let err = CatalogLayer::parse(source, CatalogFormat::Toml, "my-catalog.toml").err().unwrap();
+        assert!(matches!(err, CatalogError::Parse { .. }));
+        assert!(err.to_string().contains("my-catalog.toml"));
+    }
+
+    #[test]
+    fn test_localized_patterns() {
+        let mut catalog = ERRORS.clone();
+        assert!(catalog.localize("de-DE"));
+        let info = catalog.get(2339).unwrap();
+        assert_eq!(info.params("Die Eigenschaft \"foo\" ist für den Typ \"Bar\" nicht vorhanden.").unwrap(), vec!["foo", "Bar"]);
+        // English output still matches.
+        assert_eq!(info.params("Property 'foo' does not exist on type 'Bar'.").unwrap(), vec!["foo", "Bar"]);
+
+        let (code, _, params) = catalog.find_match("Der Typ \"string\" kann dem Typ \"number\" nicht zugewiesen werden.").unwrap();
+        assert_eq!(code, 2322);
+        assert_eq!(params, vec!["string", "number"]);
+
+        assert!(catalog.localize("en-US"));
+        assert!(!catalog.localize("tlh"));
+        assert!(catalog.get(2339).unwrap().localized.is_none());
+    }
+
+    #[test]
+    fn test_localized_placeholder_order() {
+        let pattern = LocalizedPattern::new("Typ {1} fehlt in \"{0}\" ({1}).").unwrap();
+        assert_eq!(pattern.params("Typ B fehlt in \"a\" (B).", 2).unwrap(), vec!["a", "B"]);
+    }
+
+    #[test]
+    fn test_localized_pattern_may_leave_out_params() {
+        let pattern = LocalizedPattern::new("Das Modul \"{0}\" hat keinen Export \"{2}\".").unwrap();
+        assert_eq!(pattern.params("Das Modul \"a\" hat keinen Export \"b\".", 3).unwrap(), vec!["a", "…", "b"]);
+        let pattern = LocalizedPattern::new("Der Typ \"{0}\" ist ungültig.").unwrap();
+        assert_eq!(pattern.params("Der Typ \"X\" ist ungültig.", 2).unwrap(), vec!["X", "…"]);
+    }
+
+    #[test]
+    fn test_builtin_locales_match_english_placeholders() {
+        for (locale, patterns) in LOCALE_PATTERNS.iter() {
+            for (code, pattern) in patterns {
+                let Some(english) = ERRORS.known_patterns.get(code) else {
+                    continue;
+                };
+                // A translation may leave a parameter out, but never has one of its own.
+                let expected: std::collections::BTreeSet<usize> = placeholders(english).into_iter().collect();
+                let found: std::collections::BTreeSet<usize> = placeholders(&pattern.text).into_iter().collect();
+                assert!(found.is_subset(&expected), "TS{} in {}", code, locale);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_match_by_text() {
+        let (code, _, params) = ERRORS
+            .find_match("Type 'string' is not assignable to type 'number'.")
+            .unwrap();
+        assert_eq!(code, 2322);
+        assert_eq!(params, vec!["string", "number"]);
+    }
 }
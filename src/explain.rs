@@ -0,0 +1,123 @@
+use crate::errors::{substitute_params, Catalog, ErrorInfo};
+use crate::translator::{explain_message, extract_error_code};
+use regex::Regex;
+use std::io::Write;
+use std::sync::LazyLock;
+
+/// A bare code such as `TS2345`, `ts2345` or `2345`.
+static CODE_ONLY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^(?:ts)?(\d+)$").unwrap());
+
+/// Everything up to and including `TS1234: ` in tsc or editor output.
+static CODE_PREFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^.*?\bts\d+:\s*").unwrap());
+
+/// Prints what the catalog knows about `query`, which is either an error code
+/// or a full error message. Returns `false` if nothing matched.
+pub fn explain<W: Write>(catalog: &Catalog, query: &str, output: &mut W) -> std::io::Result<bool> {
+    let query = query.trim();
+
+    if let Some(caps) = CODE_ONLY.captures(query) {
+        let Some((code, info)) = caps[1].parse().ok().and_then(|c| catalog.get(c).map(|i| (c, i))) else {
+            return Ok(false);
+        };
+        write_entry(catalog, code, info, None, output)?;
+        return Ok(true);
+    }
+
+    let message = CODE_PREFIX.replace(query, "");
+    let found = match extract_error_code(query) {
+        Some(code) => catalog.get(code).map(|info| (code, info)),
+        None => catalog.find_match(&message).map(|(code, info, _)| (code, info)),
+    };
+    let Some((code, info)) = found else {
+        return Ok(false);
+    };
+    write_entry(catalog, code, info, Some(&message), output)?;
+    Ok(true)
+}
+
+fn write_entry<W: Write>(
+    catalog: &Catalog,
+    code: u32,
+    info: &ErrorInfo,
+    message: Option<&str>,
+    output: &mut W,
+) -> std::io::Result<()> {
+    writeln!(output, "TS{}", code)?;
+    writeln!(output)?;
+    writeln!(output, "Pattern:      {}", info.pattern_text)?;
+    if let Some(localized) = &info.localized {
+        writeln!(output, "Localized:    {}", localized.text)?;
+    }
+
+    let headline = message.map(|m| m.lines().next().unwrap_or_default().trim());
+    let params = headline.and_then(|h| info.params(h));
+    match &params {
+        Some(params) => {
+            writeln!(output, "Translation:  {}", substitute_params(&info.message, params))?;
+            writeln!(output)?;
+            writeln!(output, "Parameters:")?;
+            for (i, param) in params.iter().enumerate() {
+                writeln!(output, "  {{{}}} = {}", i, param)?;
+            }
+        }
+        None => {
+            writeln!(output, "Translation:  {}", info.message)?;
+            if message.is_some() {
+                writeln!(output)?;
+                writeln!(output, "(the message does not match the pattern, so no parameters were filled in)")?;
+            }
+        }
+    }
+
+    let body = match message {
+        Some(message) => explain_message(catalog, message, code),
+        None => info.body.clone(),
+    };
+    if let Some(body) = body {
+        writeln!(output)?;
+        writeln!(output, "{}", body.trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ERRORS;
+
+    fn run(query: &str) -> Option<String> {
+        let mut output = Vec::new();
+        explain(&ERRORS, query, &mut output)
+            .unwrap()
+            .then(|| String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_explain_code() {
+        let output = run("TS2345").unwrap();
+        assert!(output.starts_with("TS2345\n"));
+        assert!(output.contains("Pattern:      Argument of type '{0}' is not assignable to parameter of type '{1}'."));
+        assert!(output.contains("Translation:  I was expecting '{1}' but you passed '{0}'."));
+    }
+
+    #[test]
+    fn test_explain_full_message() {
+        let output = run("error TS2345: Argument of type 'number' is not assignable to parameter of type 'string'.").unwrap();
+        assert!(output.contains("Translation:  I was expecting 'string' but you passed 'number'."));
+        assert!(output.contains("  {0} = number\n  {1} = string\n"));
+        assert!(output.contains("greet(42)"));
+    }
+
+    #[test]
+    fn test_explain_message_without_code() {
+        let output = run("Cannot find name 'foo'.").unwrap();
+        assert!(output.starts_with("TS2304\n"));
+        assert!(output.contains("  {0} = foo\n"));
+    }
+
+    #[test]
+    fn test_unknown_code() {
+        assert!(run("TS99999").is_none());
+        assert!(run("Something completely different").is_none());
+    }
+}
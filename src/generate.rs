@@ -0,0 +1,220 @@
+use crate::errors::{CatalogLayer, KnownMessage, PatternSet};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+const HEADER: &str = "# Generated by `ts-error-translator-proxy generate-catalog`; do not edit.\n";
+
+/// An entry of TypeScript's `diagnosticMessages.json`, keyed by message text.
+#[derive(Deserialize)]
+struct DiagnosticMessage {
+    category: String,
+    code: u32,
+}
+
+/// What the generator found while merging.
+#[derive(Default)]
+pub struct Report {
+    /// Codes in `diagnosticMessages.json`
+    pub total: usize,
+    /// Codes with a hand-written translation
+    pub translated: usize,
+    /// Codes (and their message) without a translation
+    pub untranslated: Vec<(u32, String)>,
+    /// Translations whose own pattern differs from TypeScript's message
+    pub differing: Vec<(u32, String, String)>,
+    /// Translated codes that `diagnosticMessages.json` does not have
+    pub unknown: Vec<u32>,
+}
+
+/// Parses TypeScript's `diagnosticMessages.json` into its messages by code.
+pub fn parse_diagnostic_messages(source: &str) -> Result<BTreeMap<u32, KnownMessage>, String> {
+    let messages: HashMap<String, DiagnosticMessage> =
+        serde_json::from_str(source).map_err(|e| format!("invalid diagnosticMessages.json: {}", e))?;
+
+    Ok(messages
+        .into_iter()
+        .map(|(pattern, message)| {
+            let known = KnownMessage {
+                code: message.code,
+                category: message.category,
+                pattern,
+            };
+            (message.code, known)
+        })
+        .collect())
+}
+
+/// Turns `diagnosticMessages.json` into the contents of `catalog/patterns.toml`,
+/// comparing it against the hand-written `translations`.
+pub fn generate(diagnostic_messages: &str, translations: &CatalogLayer) -> Result<(String, Report), String> {
+    let by_code = parse_diagnostic_messages(diagnostic_messages)?;
+
+    let mut report = Report {
+        total: by_code.len(),
+        ..Report::default()
+    };
+    let translated: HashMap<u32, Option<&str>> = translations.translations().collect();
+    for (code, known) in &by_code {
+        match translated.get(code) {
+            Some(Some(pattern)) if *pattern != known.pattern => {
+                report.differing.push((*code, pattern.to_string(), known.pattern.clone()));
+            }
+            Some(_) => {}
+            None => report.untranslated.push((*code, known.pattern.clone())),
+        }
+    }
+    report.translated = report.total - report.untranslated.len();
+    report.unknown = translated.keys().filter(|code| !by_code.contains_key(code)).copied().collect();
+    report.unknown.sort_unstable();
+
+    let set = PatternSet {
+        messages: by_code.into_values().collect(),
+    };
+    let body = toml::to_string(&set).map_err(|e| e.to_string())?;
+    Ok((format!("{}\n{}", HEADER, body), report))
+}
+
+/// Turns a localized `diagnosticMessages.generated.json` from TypeScript's
+/// `lib/<locale>/` into a locale pattern set. Its keys end in the message's code.
+///
+/// Returns the file contents and the number of messages.
+pub fn generate_locale(localized_messages: &str) -> Result<(String, usize), String> {
+    let messages: HashMap<String, String> = serde_json::from_str(localized_messages)
+        .map_err(|e| format!("invalid diagnosticMessages.generated.json: {}", e))?;
+
+    let by_code: BTreeMap<u32, KnownMessage> = messages
+        .into_iter()
+        .filter_map(|(key, pattern)| {
+            let code = key.rsplit_once('_')?.1.parse().ok()?;
+            let known = KnownMessage {
+                code,
+                category: String::new(),
+                pattern,
+            };
+            Some((code, known))
+        })
+        .collect();
+    let count = by_code.len();
+
+    let set = PatternSet {
+        messages: by_code.into_values().collect(),
+    };
+    let body = toml::to_string(&set).map_err(|e| e.to_string())?;
+    Ok((format!("{}\n{}", HEADER, body), count))
+}
+
+pub fn write_report<W: Write>(report: &Report, output: &mut W) -> std::io::Result<()> {
+    writeln!(
+        output,
+        "{} of {} codes have a friendly translation ({} without).",
+        report.translated,
+        report.total,
+        report.untranslated.len()
+    )?;
+
+    if !report.differing.is_empty() {
+        writeln!(output)?;
+        writeln!(output, "Translations whose pattern differs from TypeScript's message:")?;
+        for (code, ours, theirs) in &report.differing {
+            writeln!(output, "  TS{}", code)?;
+            writeln!(output, "    catalog:    {}", ours)?;
+            writeln!(output, "    TypeScript: {}", theirs)?;
+        }
+    }
+
+    if !report.unknown.is_empty() {
+        writeln!(output)?;
+        let codes: Vec<String> = report.unknown.iter().map(|code| format!("TS{}", code)).collect();
+        writeln!(output, "Translated codes missing from diagnosticMessages.json: {}", codes.join(", "))?;
+    }
+
+    if !report.untranslated.is_empty() {
+        writeln!(output)?;
+        writeln!(output, "Codes without a friendly translation:")?;
+        for (code, message) in &report.untranslated {
+            writeln!(output, "  TS{}  {}", code, message)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::CatalogFormat;
+
+    const MESSAGES: &str = r#"{
+        "Cannot find name '{0}'.": {"category": "Error", "code": 2304},
+        "'{0}' declarations must be initialized.": {"category": "Error", "code": 1155},
+        "Duplicate identifier '{0}'.": {"category": "Error", "code": 2300, "reportsUnnecessary": true}
+    }"#;
+
+    #[test]
+    fn test_generate_patterns_and_report() {
+        let translations = CatalogLayer::parse(
+            "[[error]]\ncode = 2304\nmessage = \"Not found: {0}\"\n\n\
+             [[error]]\ncode = 1155\npattern = \"Something else.\"\nmessage = \"x\"\n\n\
+             [[error]]\ncode = 95050\npattern = \"Convert\"\nmessage = \"y\"\n",
+            CatalogFormat::Toml,
+            "test",
+        )
+        .unwrap();
+        let (output, report) = generate(MESSAGES, &translations).unwrap();
+
+        assert!(output.starts_with(HEADER));
+        let set: PatternSet = toml::from_str(&output).unwrap();
+        let codes: Vec<u32> = set.messages.iter().map(|m| m.code).collect();
+        assert_eq!(codes, vec![1155, 2300, 2304]);
+
+        assert_eq!(report.total, 3);
+        assert_eq!(report.translated, 2);
+        assert_eq!(report.untranslated, vec![(2300, "Duplicate identifier '{0}'.".to_string())]);
+        assert_eq!(report.differing[0].0, 1155);
+        assert_eq!(report.unknown, vec![95050]);
+    }
+
+    #[test]
+    fn test_generate_locale() {
+        let source = r#"{
+            "Cannot_find_name_0_2304": "Der Name \"{0}\" wurde nicht gefunden.",
+            "Type_0_is_not_assignable_to_type_1_2322": "Der Typ \"{0}\" kann dem Typ \"{1}\" nicht zugewiesen werden."
+        }"#;
+        let (output, count) = generate_locale(source).unwrap();
+        assert_eq!(count, 2);
+
+        let set: PatternSet = toml::from_str(&output).unwrap();
+        assert_eq!(set.messages[0].code, 2304);
+        assert_eq!(set.messages[1].pattern, "Der Typ \"{0}\" kann dem Typ \"{1}\" nicht zugewiesen werden.");
+        assert!(!output.contains("category"));
+    }
+
+    #[test]
+    fn test_patterns_cover_vendored_messages() {
+        let vendored = parse_diagnostic_messages(include_str!("../catalog/diagnosticMessages.json")).unwrap();
+        let patterns: PatternSet = toml::from_str(include_str!("../catalog/patterns.toml")).unwrap();
+        let generated: BTreeMap<u32, &str> = patterns.messages.iter().map(|m| (m.code, m.pattern.as_str())).collect();
+
+        for (code, known) in &vendored {
+            assert_eq!(
+                generated.get(code),
+                Some(&known.pattern.as_str()),
+                "TS{} is missing or out of date in patterns.toml; rerun generate-catalog",
+                code
+            );
+        }
+        assert_eq!(generated.len(), vendored.len(), "patterns.toml has codes diagnosticMessages.json does not");
+    }
+
+    #[test]
+    fn test_locale_patterns_match_vendored_messages() {
+        let locales = [
+            ("de", include_str!("../catalog/locales/de.json"), include_str!("../catalog/locales/de.toml")),
+            ("ja", include_str!("../catalog/locales/ja.json"), include_str!("../catalog/locales/ja.toml")),
+        ];
+        for (locale, messages, patterns) in locales {
+            let (generated, _) = generate_locale(messages).unwrap();
+            assert!(generated == patterns, "catalog/locales/{0}.toml is out of date; rerun generate-catalog --locale {0}", locale);
+        }
+    }
+}
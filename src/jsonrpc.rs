@@ -1,35 +1,241 @@
+use serde_json::Value;
+use std::fmt;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 
+/// Largest message body `read_message` accepts unless told otherwise.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+/// Longest header line, and longest header section, `read_message` accepts.
+const MAX_HEADER_LINE: usize = 1 << 10;
+const MAX_HEADER_SIZE: usize = 8 << 10;
+
+/// How much of a skipped body is kept to find its `id` and `method`.
+const SKIPPED_HEAD_SIZE: usize = 4 << 10;
+
+/// The `id` and `method` of a skipped message, as far as its first bytes show.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Skipped {
+    pub id: Option<Value>,
+    pub method: Option<String>,
+}
+
+impl Skipped {
+    /// Picks the top-level `id` and `method` out of the start of a JSON body.
+    fn from_head(head: &[u8]) -> Self {
+        let mut skipped = Skipped::default();
+        let mut depth = 0;
+        let mut i = 0;
+        while i < head.len() {
+            match head[i] {
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                b'"' => {
+                    let Some(end) = string_end(head, i) else {
+                        break;
+                    };
+                    let rest = &head[end..];
+                    let colon = rest.iter().position(|b| !b.is_ascii_whitespace()).filter(|&at| rest[at] == b':');
+                    if let Some(colon) = colon.filter(|_| depth == 1) {
+                        let value = complete_value(&rest[colon + 1..]);
+                        match &head[i + 1..end - 1] {
+                            b"id" => skipped.id = value.filter(|id| id.is_number() || id.is_string()),
+                            b"method" => skipped.method = value.and_then(|method| method.as_str().map(str::to_string)),
+                            _ => {}
+                        }
+                    }
+                    i = end;
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        skipped
+    }
+
+    /// A request, which its sender waits on an answer to.
+    pub fn is_request(&self) -> bool {
+        self.id.is_some() && self.method.is_some()
+    }
+}
+
+/// Index just past the string starting at `head[start]`; `None` if it is cut off.
+fn string_end(head: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < head.len() {
+        match head[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// The JSON value at the start of `bytes`, unless it may be cut off.
+fn complete_value(bytes: &[u8]) -> Option<Value> {
+    let mut values = serde_json::Deserializer::from_slice(bytes).into_iter::<Value>();
+    let value = values.next()?.ok()?;
+    (values.byte_offset() < bytes.len()).then_some(value)
+}
+
+/// Why a message could not be read.
+#[derive(Debug)]
+pub enum MessageError {
+    Io(std::io::Error),
+    /// A header line without a `:`
+    MalformedHeader(String),
+    /// A header line or section over the limit
+    HeaderTooLarge { max: usize },
+    MissingContentLength,
+    InvalidContentLength(String),
+    /// A `Content-Type` charset other than UTF-8; the body was skipped
+    UnsupportedCharset { charset: String, skipped: Skipped },
+    /// A body over the size limit; it was skipped without being read into memory
+    TooLarge { length: usize, max: usize, skipped: Skipped },
+    /// The stream ended partway through a message
+    UnexpectedEof,
+}
+
+impl MessageError {
+    /// Whether the message was skipped whole, so the next one can still be read.
+    pub fn is_recoverable(&self) -> bool {
+        self.skipped().is_some()
+    }
+
+    /// A message that was not framed as one, after which reading can go on:
+    /// later messages are found again once the bad bytes have been read past.
+    pub fn is_framing(&self) -> bool {
+        matches!(
+            self,
+            MessageError::MalformedHeader(_)
+                | MessageError::HeaderTooLarge { .. }
+                | MessageError::MissingContentLength
+                | MessageError::InvalidContentLength(_)
+        )
+    }
+
+    /// What is known of the skipped message, for a recoverable error.
+    pub fn skipped(&self) -> Option<&Skipped> {
+        match self {
+            MessageError::UnsupportedCharset { skipped, .. } | MessageError::TooLarge { skipped, .. } => Some(skipped),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Io(e) => write!(f, "{}", e),
+            MessageError::MalformedHeader(line) => write!(f, "malformed header line '{}'", line),
+            MessageError::HeaderTooLarge { max } => write!(f, "header exceeds the limit of {} bytes", max),
+            MessageError::MissingContentLength => write!(f, "message without a Content-Length header"),
+            MessageError::InvalidContentLength(value) => write!(f, "invalid Content-Length '{}'", value),
+            MessageError::UnsupportedCharset { charset, .. } => {
+                write!(f, "unsupported charset '{}' (only utf-8 is allowed)", charset)
+            }
+            MessageError::TooLarge { length, max, .. } => {
+                write!(f, "message of {} bytes exceeds the limit of {} bytes", length, max)
+            }
+            MessageError::UnexpectedEof => write!(f, "stream ended in the middle of a message"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+impl From<std::io::Error> for MessageError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            MessageError::UnexpectedEof
+        } else {
+            MessageError::Io(e)
+        }
+    }
+}
+
+impl From<MessageError> for std::io::Error {
+    fn from(e: MessageError) -> Self {
+        match e {
+            MessageError::Io(e) => e,
+            MessageError::UnexpectedEof => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, e),
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Reads one message. Header names are case-insensitive, as the LSP spec
+/// requires, and unknown headers are ignored. `Ok(None)` when the stream ends
+/// between messages.
+///
+/// Header lines are limited to `MAX_HEADER_LINE` bytes and the whole header
+/// to `MAX_HEADER_SIZE`, so a peer that never sends a newline cannot make the
+/// proxy buffer without bound.
 pub async fn read_message<R: tokio::io::AsyncRead + Unpin>(
     reader: &mut BufReader<R>,
-) -> std::io::Result<Option<Vec<u8>>> {
+    max_size: usize,
+) -> Result<Option<Vec<u8>>, MessageError> {
     let mut content_length: Option<usize> = None;
-    let mut line = String::new();
+    let mut charset: Option<String> = None;
+    let mut line = Vec::new();
+    let mut header_size = 0;
 
     loop {
         line.clear();
-        let bytes_read = reader.read_line(&mut line).await?;
+        let limit = MAX_HEADER_LINE.min(MAX_HEADER_SIZE - header_size);
+        let bytes_read = (&mut *reader).take(limit as u64).read_until(b'\n', &mut line).await?;
+        if bytes_read == limit && !line.ends_with(b"\n") {
+            let max = if limit == MAX_HEADER_LINE { MAX_HEADER_LINE } else { MAX_HEADER_SIZE };
+            return Err(MessageError::HeaderTooLarge { max });
+        }
         if bytes_read == 0 {
-            return Ok(None);
+            return if header_size > 0 { Err(MessageError::UnexpectedEof) } else { Ok(None) };
         }
+        header_size += bytes_read;
 
-        let trimmed = line.trim();
+        let line = String::from_utf8_lossy(&line);
+        let mut trimmed = line.trim();
         if trimmed.is_empty() {
             break;
         }
+        // Bodies end without a newline, so what is left of one that was longer
+        // than its Content-Length runs into the next header. Start over there.
+        if let Some(at) = trimmed.to_ascii_lowercase().find("content-length:").filter(|&at| at > 0) {
+            trimmed = &trimmed[at..];
+        }
 
-        if let Some(len_str) = trimmed.strip_prefix("Content-Length: ") {
-            content_length = Some(
-                len_str
-                    .parse()
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
-            );
+        let Some((name, value)) = trimmed.split_once(':') else {
+            return Err(MessageError::MalformedHeader(trimmed.to_string()));
+        };
+        let value = value.trim();
+        if name.trim().eq_ignore_ascii_case("Content-Length") {
+            let length = value
+                .parse()
+                .map_err(|_| MessageError::InvalidContentLength(value.to_string()))?;
+            content_length = Some(length);
+        } else if name.trim().eq_ignore_ascii_case("Content-Type") {
+            charset = content_type_charset(value);
         }
     }
 
-    let length = content_length.ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing Content-Length")
-    })?;
+    let length = content_length.ok_or(MessageError::MissingContentLength)?;
+    let unsupported = charset.filter(|charset| !is_utf8(charset));
+    if length > max_size || unsupported.is_some() {
+        // Skip the body so the next message can still be read, keeping its
+        // start to tell who is waiting on it.
+        let mut head = vec![0u8; length.min(SKIPPED_HEAD_SIZE)];
+        reader.read_exact(&mut head).await?;
+        let rest = (length - head.len()) as u64;
+        if tokio::io::copy(&mut reader.take(rest), &mut tokio::io::sink()).await? < rest {
+            return Err(MessageError::UnexpectedEof);
+        }
+        let skipped = Skipped::from_head(&head);
+        return Err(match unsupported {
+            Some(charset) => MessageError::UnsupportedCharset { charset, skipped },
+            None => MessageError::TooLarge { length, max: max_size, skipped },
+        });
+    }
 
     let mut body = vec![0u8; length];
     reader.read_exact(&mut body).await?;
@@ -37,6 +243,21 @@ pub async fn read_message<R: tokio::io::AsyncRead + Unpin>(
     Ok(Some(body))
 }
 
+/// The `charset` parameter of a `Content-Type` value, unquoted.
+fn content_type_charset(value: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (name, charset) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| charset.trim().trim_matches('"').to_string())
+    })
+}
+
+/// `utf-8`, or `utf8`, which the spec accepts for backwards compatibility.
+fn is_utf8(charset: &str) -> bool {
+    charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+}
+
 pub async fn write_message<W: tokio::io::AsyncWrite + Unpin>(
     writer: &mut W,
     body: &[u8],
@@ -53,6 +274,10 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    fn reader(wire: &[u8]) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::new(Cursor::new(wire.to_vec()))
+    }
+
     #[tokio::test]
     async fn test_roundtrip() {
         let original = b"{\"jsonrpc\":\"2.0\",\"id\":1}";
@@ -61,7 +286,7 @@ mod tests {
         write_message(&mut wire, original).await.unwrap();
 
         let mut reader = BufReader::new(Cursor::new(wire));
-        let decoded = read_message(&mut reader).await.unwrap().unwrap();
+        let decoded = read_message(&mut reader, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap();
 
         assert_eq!(decoded, original);
     }
@@ -69,6 +294,105 @@ mod tests {
     #[tokio::test]
     async fn test_eof_returns_none() {
         let mut reader = BufReader::new(Cursor::new(Vec::new()));
-        assert!(read_message(&mut reader).await.unwrap().is_none());
+        assert!(read_message(&mut reader, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_headers_case_insensitive_with_whitespace() {
+        let wire = b"content-length:2 \r\nCONTENT-TYPE: application/vscode-jsonrpc; Charset=\"UTF8\"\r\nX-Other: 1\r\n\r\n{}";
+        let decoded = read_message(&mut reader(wire), DEFAULT_MAX_MESSAGE_SIZE).await.unwrap();
+        assert_eq!(decoded.unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_skips_unsupported_charset_and_oversized_bodies() {
+        let mut wire = reader(
+            b"Content-Length: 2\r\nContent-Type: application/vscode-jsonrpc; charset=latin1\r\n\r\n{}\
+              Content-Length: 6\r\n\r\n[1, 2]\
+              Content-Length: 4\r\n\r\nnull",
+        );
+        let error = read_message(&mut wire, 4).await.unwrap_err();
+        assert!(matches!(&error, MessageError::UnsupportedCharset { charset, .. } if charset == "latin1"));
+        assert!(error.is_recoverable());
+        let error = read_message(&mut wire, 4).await.unwrap_err();
+        assert!(matches!(error, MessageError::TooLarge { length: 6, max: 4, .. }));
+        assert_eq!(read_message(&mut wire, 4).await.unwrap().unwrap(), b"null");
+    }
+
+    #[tokio::test]
+    async fn test_skipped_message_keeps_id_and_method() {
+        let body = format!(
+            r#"{{"jsonrpc":"2.0","id":7,"method":"textDocument/didOpen","params":{{"text":"{}"}}}}"#,
+            "x".repeat(SKIPPED_HEAD_SIZE)
+        );
+        let wire = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let error = read_message(&mut reader(wire.as_bytes()), 100).await.unwrap_err();
+        let skipped = error.skipped().unwrap();
+        assert_eq!(skipped.id, Some(Value::from(7)));
+        assert_eq!(skipped.method.as_deref(), Some("textDocument/didOpen"));
+        assert!(skipped.is_request());
+    }
+
+    #[test]
+    fn test_skipped_from_head() {
+        let skipped = Skipped::from_head(br#"{"params": {"id": 1, "method": "nested"}, "id": "a\"b", "result": {"#);
+        assert_eq!(skipped.id, Some(Value::from("a\"b")));
+        assert_eq!(skipped.method, None);
+        assert!(!skipped.is_request());
+
+        // Cut off inside the value, or right after a number that may go on
+        assert_eq!(Skipped::from_head(br#"{"method": "textDocum"#), Skipped::default());
+        assert_eq!(Skipped::from_head(br#"{"id": 12"#).id, None);
+        assert_eq!(Skipped::from_head(b"not json"), Skipped::default());
+    }
+
+    #[tokio::test]
+    async fn test_header_size_is_limited() {
+        let long_line = format!("X-Padding: {}\r\n", "x".repeat(MAX_HEADER_LINE));
+        let wire = format!("{}Content-Length: 2\r\n\r\n{{}}", long_line);
+        let error = read_message(&mut reader(wire.as_bytes()), DEFAULT_MAX_MESSAGE_SIZE).await.unwrap_err();
+        assert!(matches!(error, MessageError::HeaderTooLarge { max: MAX_HEADER_LINE }));
+
+        let many_lines = "X-Padding: x\r\n".repeat(MAX_HEADER_SIZE / 14 + 1);
+        let error = read_message(&mut reader(many_lines.as_bytes()), DEFAULT_MAX_MESSAGE_SIZE).await.unwrap_err();
+        assert!(matches!(error, MessageError::HeaderTooLarge { max: MAX_HEADER_SIZE }));
+        assert!(!error.is_recoverable());
+
+        let wire = format!("{}Content-Length: 2\r\n\r\n{{}}", "X-Padding: x\r\n".repeat(100));
+        assert_eq!(read_message(&mut reader(wire.as_bytes()), DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_structured_errors() {
+        let read = |wire: &'static [u8]| async move { read_message(&mut reader(wire), DEFAULT_MAX_MESSAGE_SIZE).await };
+        assert!(matches!(read(b"Content-Length 2\r\n\r\n{}").await, Err(MessageError::MalformedHeader(_))));
+        assert!(matches!(read(b"Content-Type: x\r\n\r\n{}").await, Err(MessageError::MissingContentLength)));
+        assert!(matches!(read(b"Content-Length: -1\r\n\r\n").await, Err(MessageError::InvalidContentLength(_))));
+        assert!(matches!(read(b"Content-Length: 2\r\n").await, Err(MessageError::UnexpectedEof)));
+        assert!(matches!(read(b"Content-Length: 9\r\n\r\n{}").await, Err(MessageError::UnexpectedEof)));
+
+        let error: std::io::Error = MessageError::MissingContentLength.into();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_reads_on_after_framing_errors() {
+        let mut wire = reader(b"garbage\r\nContent-Length: x\r\n\r\nContent-Length: 4\r\n\r\nnull");
+        let error = read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap_err();
+        assert!(matches!(error, MessageError::MalformedHeader(_)) && error.is_framing());
+        let error = read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap_err();
+        assert!(matches!(error, MessageError::InvalidContentLength(_)) && error.is_framing());
+        // The rest of that header, up to the blank line
+        assert!(matches!(read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await, Err(MessageError::MissingContentLength)));
+        assert_eq!(read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"null");
+        assert!(!MessageError::UnexpectedEof.is_framing());
+    }
+
+    #[tokio::test]
+    async fn test_finds_header_after_short_content_length() {
+        let mut wire = reader(b"Content-Length: 5\r\n\r\n{\"a\": 1}Content-Length: 4\r\n\r\nnullcontent-length: 2\r\n\r\n{}");
+        assert_eq!(read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"{\"a\":");
+        assert_eq!(read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"null");
+        assert_eq!(read_message(&mut wire, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"{}");
     }
 }
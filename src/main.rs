@@ -1,66 +1,719 @@
+mod check;
+mod commands;
+mod config;
 mod errors;
+mod explain;
+mod generate;
 mod jsonrpc;
+mod policy;
 mod proxy;
+mod store;
+mod supervisor;
+mod transport;
 mod translator;
+mod trace;
+mod tsc;
+mod types;
+mod workspace;
 
-use std::process::Stdio;
-use tokio::process::Command;
+use errors::{Catalog, CatalogLayer, ERRORS};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use supervisor::LanguageServer;
+use trace::Trace;
+use tokio::io::{AsyncRead, AsyncWrite};
+use transport::Address;
 
-#[derive(Clone, Copy)]
+/// Exit code for failures of the proxy itself, as opposed to the server's own status (EX_SOFTWARE).
+const EXIT_PROXY_ERROR: i32 = 70;
+
+/// How long the server gets to exit once the session is over, before it is killed.
+const DEFAULT_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, doubling while failures continue (e.g. out of file descriptors).
+const FIRST_ACCEPT_DELAY: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TranslationMode {
+    /// Original message, then the translation on the same line
     Append,
+    /// Translation only
     Replace,
+    /// Translation, then the original message on the same line
+    Prepend,
+    /// Original message with the translation on its own line below
+    Newline,
+    /// Original message untouched; translation added as a `relatedInformation` entry
+    Related,
+    /// Original message untouched; translation published as a separate
+    /// `Information` diagnostic at the same range
+    Diagnostic,
+}
+
+impl TranslationMode {
+    fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    /// Whether the translation is written into the diagnostic's own message.
+    pub fn rewrites_message(self) -> bool {
+        !matches!(self, TranslationMode::Related | TranslationMode::Diagnostic)
+    }
 }
 
 fn print_usage() {
     eprintln!("Usage: ts-error-translator-proxy [OPTIONS] [LSP_COMMAND] [LSP_ARGS...]");
+    eprintln!("       ts-error-translator-proxy translate [OPTIONS] [-- TSC_COMMAND [ARGS...]]");
+    eprintln!("       ts-error-translator-proxy explain [OPTIONS] <CODE | MESSAGE>");
+    eprintln!("       ts-error-translator-proxy generate-catalog [--locale LOCALE] [--messages PATH] [--translations PATH] [--output PATH]");
+    eprintln!("       ts-error-translator-proxy check-catalog [OPTIONS] [--messages PATH]");
+    eprintln!("       ts-error-translator-proxy replay [OPTIONS] <TRACE>");
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!("  translate    Annotate tsc output from stdin, or from running TSC_COMMAND");
+    eprintln!("  explain      Show the pattern, translation and explanation for an error");
+    eprintln!("  generate-catalog");
+    eprintln!("               Regenerate catalog/patterns.toml from TypeScript's diagnosticMessages.json,");
+    eprintln!("               or catalog/locales/LOCALE.toml from its lib/LOCALE/diagnosticMessages.generated.json");
+    eprintln!("  check-catalog");
+    eprintln!("               Validate placeholders and patterns, optionally against diagnosticMessages.json");
+    eprintln!("  replay       Run the server's messages from a --trace file through the proxy and show what changes");
     eprintln!();
     eprintln!("Options:");
-    eprintln!("  --append     Append translation to original message instead of replacing");
-    eprintln!("  --help       Show this help");
+    eprintln!("  --append               Append translation to original message instead of replacing");
+    eprintln!("  --mode <MODE>          replace, append, prepend, newline, related or diagnostic");
+    eprintln!("  --template <TEMPLATE>  Preset (helix, zed) or template for translated lines");
+    eprintln!("  --docs-url <URL>       upstream, typescript.tv, none, or a template like https://wiki/{{code}}");
+    eprintln!("  --catalog <PATH>       Load extra translations from a TOML or JSON catalog (repeatable)");
+    eprintln!("  --no-builtin-catalog   Start from an empty catalog instead of the built-in one");
+    eprintln!("  --locale <LOCALE>      Also match TypeScript messages in this locale (e.g. de, ja)");
+    eprintln!("  --exit-timeout <SECS>  Time the server gets to exit after the session ends (default 5)");
+    eprintln!("  --listen <ADDR>        Accept editors on a port, host:port or socket path instead of stdio");
+    eprintln!("  --port <[HOST:]PORT>   Connect to a language server listening on TCP instead of starting one");
+    eprintln!("  --socket <PATH>        Connect to a language server on a Unix socket or named pipe");
+    eprintln!("  --trace <PATH>         Record every message to PATH as JSON lines");
+    eprintln!("  --max-message-size <MIB>");
+    eprintln!("                         Skip messages larger than this (default 64)");
+    eprintln!("  --help                 Show this help");
     eprintln!();
     eprintln!("Default LSP: vtsls --stdio");
 }
 
+/// Catalog flags shared by the proxy and the subcommands.
+struct CatalogArgs {
+    paths: Vec<PathBuf>,
+    builtin: bool,
+    locale: Option<String>,
+}
+
+impl CatalogArgs {
+    fn new() -> Self {
+        Self {
+            paths: Vec::new(),
+            builtin: true,
+            locale: None,
+        }
+    }
+
+    /// Consumes `arg` (and its value from `rest`) if it is a catalog flag.
+    fn parse(&mut self, arg: &str, rest: &mut std::slice::Iter<'_, String>) -> bool {
+        match arg {
+            "--catalog" => {
+                let Some(path) = rest.next() else {
+                    eprintln!("error: --catalog requires a path");
+                    std::process::exit(2);
+                };
+                self.paths.push(PathBuf::from(path));
+                true
+            }
+            "--no-builtin-catalog" => {
+                self.builtin = false;
+                true
+            }
+            "--locale" => {
+                let Some(locale) = rest.next() else {
+                    eprintln!("error: --locale requires a TypeScript locale such as de or ja");
+                    std::process::exit(2);
+                };
+                self.locale = Some(locale.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn build(&self) -> Catalog {
+        let mut catalog = build_catalog(&self.paths, self.builtin);
+        if let Some(locale) = &self.locale {
+            if !catalog.localize(locale) {
+                eprintln!("error: no TypeScript patterns for locale '{}'", locale);
+                std::process::exit(2);
+            }
+        }
+        catalog
+    }
+}
+
+/// Presentation flags shared by the proxy and `replay`.
+struct ConfigArgs {
+    mode: TranslationMode,
+    template: Option<String>,
+    docs_url: Option<String>,
+}
+
+impl ConfigArgs {
+    fn new() -> Self {
+        Self {
+            mode: TranslationMode::Replace,
+            template: None,
+            docs_url: None,
+        }
+    }
+
+    /// Consumes `arg` (and its value from `rest`) if it is a presentation flag.
+    fn parse(&mut self, arg: &str, rest: &mut std::slice::Iter<'_, String>) -> bool {
+        match arg {
+            "--append" => {
+                self.mode = TranslationMode::Append;
+                true
+            }
+            "--mode" => {
+                let Some(parsed) = rest.next().and_then(|name| TranslationMode::parse(name)) else {
+                    eprintln!("error: --mode requires one of replace, append, prepend, newline, related, diagnostic");
+                    std::process::exit(2);
+                };
+                self.mode = parsed;
+                true
+            }
+            "--template" => {
+                let Some(value) = rest.next() else {
+                    eprintln!("error: --template requires a preset name or a template");
+                    std::process::exit(2);
+                };
+                self.template = Some(value.clone());
+                true
+            }
+            "--docs-url" => {
+                let Some(value) = rest.next() else {
+                    eprintln!("error: --docs-url requires a site name or URL template");
+                    std::process::exit(2);
+                };
+                self.docs_url = Some(value.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn build(&self, locale: Option<String>) -> config::Config {
+        let mut config = config::Config::new(self.mode);
+        config.locale = locale;
+        if let Some(template) = &self.template {
+            config.style.set_template(template);
+        }
+        if let Some(docs_url) = &self.docs_url {
+            config.style.set_docs_url(docs_url);
+        }
+        config
+    }
+}
+
+/// Starts from the built-in catalog (unless disabled), then layers the user
+/// catalog and the `--catalog` files on top, so that flags win over the
+/// user's defaults. Exits on invalid `--catalog` files.
+fn build_catalog(paths: &[PathBuf], builtin: bool) -> Catalog {
+    let base = if builtin {
+        ERRORS.clone()
+    } else {
+        Catalog::default()
+    };
+    let user = workspace::user_catalog_path();
+    layer_catalogs(base, user.as_deref(), paths).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Layers `user` (skipped with a warning when invalid), then `paths`, over `catalog`.
+fn layer_catalogs(mut catalog: Catalog, user: Option<&Path>, paths: &[PathBuf]) -> Result<Catalog, errors::CatalogError> {
+    if let Some(path) = user {
+        if let Err(e) = CatalogLayer::load(path).and_then(|layer| catalog.apply(&layer)) {
+            eprintln!("ts-error-translator-proxy: ignoring user catalog: {}", e);
+        }
+    }
+    for path in paths {
+        CatalogLayer::load(path).and_then(|layer| catalog.apply(&layer))?;
+    }
+    Ok(catalog)
+}
+
+/// `translate [--catalog PATH]... [--no-builtin-catalog] [--template T] [--docs-url URL] [--] [TSC_COMMAND ARGS...]`
+///
+/// Settings come from the flags and then the project config file found from
+/// the current directory, as in the proxy.
+fn run_translate(args: &[String]) -> std::io::Result<()> {
+    let mut catalog_args = CatalogArgs::new();
+    let mut config_args = ConfigArgs::new();
+    let mut command: Vec<String> = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if matches!(arg.as_str(), "--mode" | "--append") {
+            eprintln!("error: translate always prints translations on their own line; {} does not apply", arg);
+            std::process::exit(2);
+        }
+        if catalog_args.parse(arg, &mut iter) || config_args.parse(arg, &mut iter) {
+            continue;
+        }
+        match arg.as_str() {
+            "--" => {
+                command.extend(iter.by_ref().cloned());
+            }
+            _ => {
+                command.push(arg.clone());
+                command.extend(iter.by_ref().cloned());
+            }
+        }
+    }
+
+    let mut config = config_args.build(catalog_args.locale.clone());
+    let root = std::env::current_dir()?;
+    if let Some(settings) = config::load_project_settings(&root) {
+        let unknown = settings.unknown_keys();
+        if !unknown.is_empty() {
+            eprintln!("ts-error-translator-proxy: ignoring unknown settings: {}", unknown.join(", "));
+        }
+        config.merge(settings);
+    }
+    let catalog = if config.enabled {
+        proxy::session_catalog(&catalog_args.build(), &config, Some(&root), None)
+    } else {
+        Catalog::default()
+    };
+    let style = &config.style;
+
+    let Some((program, program_args)) = command.split_first() else {
+        return tsc::annotate(&catalog, style, std::io::stdin().lock(), std::io::stdout().lock());
+    };
+
+    let mut child = std::process::Command::new(program)
+        .args(program_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("Failed to open tsc stdout");
+    tsc::annotate(&catalog, style, std::io::BufReader::new(stdout), std::io::stdout().lock())?;
+
+    let status = child.wait()?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// `explain [--catalog PATH]... [--no-builtin-catalog] <CODE | MESSAGE>`
+fn run_explain(args: &[String]) -> std::io::Result<()> {
+    let mut catalog_args = CatalogArgs::new();
+    let mut query: Vec<&str> = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !catalog_args.parse(arg, &mut iter) {
+            query.push(arg);
+        }
+    }
+
+    if query.is_empty() {
+        eprintln!("error: explain requires an error code (e.g. TS2345) or a message");
+        std::process::exit(2);
+    }
+    let query = query.join(" ");
+
+    let catalog = catalog_args.build();
+    if !explain::explain(&catalog, &query, &mut std::io::stdout().lock())? {
+        eprintln!("error: no translation found for '{}'", query);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `check-catalog [--catalog PATH]... [--no-builtin-catalog] [--messages PATH]`
+fn run_check(args: &[String]) -> std::io::Result<()> {
+    let mut catalog_args = CatalogArgs::new();
+    let mut messages: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if catalog_args.parse(arg, &mut iter) {
+            continue;
+        }
+        match arg.as_str() {
+            "--messages" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("error: --messages requires a path");
+                    std::process::exit(2);
+                };
+                messages = Some(PathBuf::from(path));
+            }
+            _ => {
+                eprintln!("error: unknown check-catalog option '{}'", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let known = match &messages {
+        Some(path) => Some(generate::parse_diagnostic_messages(&std::fs::read_to_string(path)?).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        })),
+        None => None,
+    };
+    let catalog = catalog_args.build();
+    if check::check_catalog(&catalog, known.as_ref(), &mut std::io::stdout().lock())? > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `generate-catalog [--locale LOCALE] [--messages PATH] [--translations PATH] [--output PATH]`,
+/// run from the repository root.
+fn run_generate(args: &[String]) -> std::io::Result<()> {
+    let mut locale: Option<String> = None;
+    let mut messages: Option<PathBuf> = None;
+    let mut translations = PathBuf::from("catalog/errors.toml");
+    let mut output: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Some(value) = iter.next() else {
+            eprintln!("error: {} requires a value", arg);
+            std::process::exit(2);
+        };
+        match arg.as_str() {
+            "--locale" => locale = Some(value.to_ascii_lowercase()),
+            "--messages" => messages = Some(PathBuf::from(value)),
+            "--translations" => translations = PathBuf::from(value),
+            "--output" => output = Some(PathBuf::from(value)),
+            _ => {
+                eprintln!("error: unknown generate-catalog option '{}'", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if let Some(locale) = &locale {
+        let messages = messages.unwrap_or_else(|| PathBuf::from(format!("catalog/locales/{}.json", locale)));
+        let output = output.unwrap_or_else(|| PathBuf::from(format!("catalog/locales/{}.toml", locale)));
+        let source = std::fs::read_to_string(&messages)?;
+        let (patterns, count) = generate::generate_locale(&source).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        });
+        std::fs::write(&output, patterns)?;
+        println!("Wrote {} ({} messages)", output.display(), count);
+        return Ok(());
+    }
+
+    let messages = messages.unwrap_or_else(|| PathBuf::from("catalog/diagnosticMessages.json"));
+    let output = output.unwrap_or_else(|| PathBuf::from("catalog/patterns.toml"));
+    let layer = CatalogLayer::load(&translations).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    let source = std::fs::read_to_string(&messages)?;
+    let (patterns, report) = generate::generate(&source, &layer).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    std::fs::write(&output, patterns)?;
+
+    println!("Wrote {}", output.display());
+    generate::write_report(&report, &mut std::io::stdout().lock())
+}
+
+/// `replay [--catalog PATH]... [--mode MODE] [--template T] [--docs-url URL] <TRACE>`
+fn run_replay(args: &[String]) -> std::io::Result<()> {
+    let mut catalog_args = CatalogArgs::new();
+    let mut config_args = ConfigArgs::new();
+    let mut path: Option<PathBuf> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if catalog_args.parse(arg, &mut iter) || config_args.parse(arg, &mut iter) {
+            continue;
+        }
+        if arg.starts_with("--") || path.is_some() {
+            eprintln!("error: unexpected replay argument '{}'", arg);
+            std::process::exit(2);
+        }
+        path = Some(PathBuf::from(arg));
+    }
+
+    let Some(path) = path else {
+        eprintln!("error: replay requires a trace file recorded with --trace");
+        std::process::exit(2);
+    };
+    let entries = trace::parse_trace(&std::fs::read_to_string(&path)?).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    });
+    let catalog = catalog_args.build();
+    let config = config_args.build(catalog_args.locale.clone());
+
+    let (total, changed) = trace::replay(&entries, &catalog, &config, &mut std::io::stdout().lock())?;
+    println!("{} of {} server messages changed.", changed, total);
+    Ok(())
+}
+
+/// What every editor session starts from.
+#[derive(Clone)]
+struct SessionOptions {
+    catalog: Catalog,
+    config: config::Config,
+    /// How long the server gets to exit once the session is over
+    exit_timeout: Duration,
+    trace: Trace,
+}
+
+/// Proxies one editor connection until it ends or `signal` fires, then stops the server.
+async fn run_session<R, W>(
+    editor_reader: R,
+    editor_writer: W,
+    mut server: LanguageServer,
+    options: SessionOptions,
+    signal: impl Future<Output = i32>,
+) -> std::io::Result<Option<ExitStatus>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (result, signal) = tokio::select! {
+        result = proxy::run_proxy(editor_reader, editor_writer, &mut server, options.catalog, options.config, &options.trace) => (result, None),
+        signal = signal => (Ok(()), Some(signal)),
+    };
+    if let Some(signal) = signal {
+        server.signal(signal);
+    }
+    let status = server.finish(options.exit_timeout).await;
+    result.and(status)
+}
+
+/// Accepts editors at `address` until SIGTERM or SIGINT, each in its own
+/// session with its own server connection. Sessions are numbered in the trace.
+async fn listen(
+    address: &Address,
+    new_server: impl Fn() -> LanguageServer,
+    options: SessionOptions,
+) -> std::io::Result<()> {
+    let mut listener = transport::Listener::bind(address).await?;
+    eprintln!("ts-error-translator-proxy: listening on {}", listener.local_address());
+
+    let (signal_sender, signals) = tokio::sync::watch::channel(None);
+    let terminated = supervisor::termination_signal();
+    tokio::pin!(terminated);
+    let sessions = tokio::task::LocalSet::new();
+    sessions
+        .run_until(async {
+            let mut accept_delay = FIRST_ACCEPT_DELAY;
+            for number in 1.. {
+                let (reader, writer) = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok(connection) => {
+                            accept_delay = FIRST_ACCEPT_DELAY;
+                            connection
+                        }
+                        Err(e) => {
+                            eprintln!("ts-error-translator-proxy: cannot accept editor: {}", e);
+                            tokio::time::sleep(accept_delay).await;
+                            accept_delay = (accept_delay * 2).min(MAX_ACCEPT_DELAY);
+                            continue;
+                        }
+                    },
+                    signal = &mut terminated => {
+                        let _ = signal_sender.send(Some(signal));
+                        break;
+                    }
+                };
+                let mut signals = signals.clone();
+                let signal = async move {
+                    loop {
+                        if let Some(signal) = *signals.borrow_and_update() {
+                            return signal;
+                        }
+                        if signals.changed().await.is_err() {
+                            return std::future::pending().await;
+                        }
+                    }
+                };
+                let options = SessionOptions {
+                    trace: options.trace.for_session(number),
+                    ..options.clone()
+                };
+                let session = run_session(reader, writer, new_server(), options, signal);
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = session.await {
+                        eprintln!("ts-error-translator-proxy: session ended with an error: {}", e);
+                    }
+                });
+            }
+        })
+        .await;
+    // Let the sessions stop their servers.
+    sessions.await;
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let args: Vec<String> = std::env::args().skip(1).collect();
 
-    let mut mode = TranslationMode::Replace;
+    match args.first().map(String::as_str) {
+        Some("translate") => return run_translate(&args[1..]),
+        Some("explain") => return run_explain(&args[1..]),
+        Some("generate-catalog") => return run_generate(&args[1..]),
+        Some("check-catalog") => return run_check(&args[1..]),
+        Some("replay") => return run_replay(&args[1..]),
+        _ => {}
+    }
+
+    let mut config_args = ConfigArgs::new();
+    let mut exit_timeout = DEFAULT_EXIT_TIMEOUT;
+    let mut listen_address: Option<Address> = None;
+    let mut server_address: Option<Address> = None;
+    let mut trace_path: Option<PathBuf> = None;
+    let mut max_message_size = jsonrpc::DEFAULT_MAX_MESSAGE_SIZE;
+    let mut catalog_args = CatalogArgs::new();
     let mut lsp_args: Vec<String> = Vec::new();
 
-    for arg in &args {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if catalog_args.parse(arg, &mut iter) || config_args.parse(arg, &mut iter) {
+            continue;
+        }
         match arg.as_str() {
             "--help" | "-h" => {
                 print_usage();
                 return Ok(());
             }
-            "--append" => mode = TranslationMode::Append,
+            "--exit-timeout" => {
+                let parsed = iter.next().and_then(|value| value.parse::<f64>().ok());
+                let Some(timeout) = parsed.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()) else {
+                    eprintln!("error: --exit-timeout requires a number of seconds");
+                    std::process::exit(2);
+                };
+                exit_timeout = timeout;
+            }
+            "--listen" => {
+                let Some(address) = iter.next().and_then(|value| Address::parse(value)) else {
+                    eprintln!("error: --listen requires a port, host:port or socket path");
+                    std::process::exit(2);
+                };
+                listen_address = Some(address);
+            }
+            "--port" => {
+                let address = iter.next().and_then(|value| Address::parse(value));
+                let Some(address) = address.filter(|address| matches!(address, Address::Tcp(_))) else {
+                    eprintln!("error: --port requires a port or host:port");
+                    std::process::exit(2);
+                };
+                server_address = Some(address);
+            }
+            "--socket" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("error: --socket requires a socket path");
+                    std::process::exit(2);
+                };
+                server_address = Some(Address::Local(PathBuf::from(path)));
+            }
+            "--trace" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("error: --trace requires a file path");
+                    std::process::exit(2);
+                };
+                trace_path = Some(PathBuf::from(path));
+            }
+            "--max-message-size" => {
+                let parsed = iter.next().and_then(|value| value.parse::<usize>().ok());
+                let Some(size) = parsed.filter(|&mib| mib > 0).and_then(|mib| mib.checked_mul(1 << 20)) else {
+                    eprintln!("error: --max-message-size requires a size in MiB");
+                    std::process::exit(2);
+                };
+                max_message_size = size;
+            }
             _ => lsp_args.push(arg.clone()),
         }
     }
 
+    if server_address.is_some() && !lsp_args.is_empty() {
+        eprintln!("error: --port and --socket connect to a running server; leave out LSP_COMMAND");
+        std::process::exit(2);
+    }
+
+    let catalog = catalog_args.build();
+
     let (cmd, cmd_args) = if lsp_args.is_empty() {
         ("vtsls".to_string(), vec!["--stdio".to_string()])
     } else {
         (lsp_args[0].clone(), lsp_args[1..].to_vec())
     };
+    let new_server = || match &server_address {
+        Some(address) => LanguageServer::Remote(address.clone()),
+        None => LanguageServer::Process(supervisor::ServerProcess::new(cmd.clone(), cmd_args.clone())),
+    };
 
-    let mut child = Command::new(&cmd)
-        .args(&cmd_args)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()?;
+    let mut config = config_args.build(catalog_args.locale.clone());
+    config.max_message_size = max_message_size;
+    let trace = match &trace_path {
+        Some(path) => Trace::create(path).await.unwrap_or_else(|e| {
+            eprintln!("error: cannot create trace file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        None => Trace::default(),
+    };
+    let options = SessionOptions {
+        catalog,
+        config,
+        exit_timeout,
+        trace: trace.clone(),
+    };
 
-    let lsp_stdin = child.stdin.take().expect("Failed to open LSP stdin");
-    let lsp_stdout = child.stdout.take().expect("Failed to open LSP stdout");
+    let result = match &listen_address {
+        Some(address) => listen(address, new_server, options).await.map(|()| None),
+        None => {
+            let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
+            let signal = supervisor::termination_signal();
+            run_session(stdin, stdout, new_server(), options, signal).await
+        }
+    };
+    trace.flush().await;
+    match result {
+        Ok(Some(status)) => std::process::exit(supervisor::exit_code(status)),
+        Ok(None) => Ok(()),
+        Err(e) => {
+            eprintln!("ts-error-translator-proxy: {}", e);
+            std::process::exit(EXIT_PROXY_ERROR);
+        }
+    }
+}
 
-    let editor_stdin = tokio::io::stdin();
-    let editor_stdout = tokio::io::stdout();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    proxy::run_proxy(editor_stdin, editor_stdout, lsp_stdout, lsp_stdin, mode).await?;
+    #[test]
+    fn test_catalog_flags_override_user_catalog() {
+        let dir = std::env::temp_dir().join(format!("ts-error-translator-layers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.toml");
+        let flag = dir.join("flag.toml");
+        std::fs::write(&user, "[[error]]\ncode = 2304\nmessage = \"User: {0}\"\n\n[[error]]\ncode = 2339\nmessage = \"User only\"\n").unwrap();
+        std::fs::write(&flag, "[[error]]\ncode = 2304\nmessage = \"Flag: {0}\"\n").unwrap();
 
-    child.wait().await?;
-    Ok(())
+        let catalog = layer_catalogs(ERRORS.clone(), Some(&user), &[flag]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(catalog.get(2304).unwrap().message, "Flag: {0}");
+        assert_eq!(catalog.get(2339).unwrap().message, "User only");
+    }
 }
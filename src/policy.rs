@@ -0,0 +1,199 @@
+use crate::config::deserialize_codes;
+use crate::TranslationMode;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// LSP `DiagnosticSeverity`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// Diagnostics without a severity count as errors, as tsserver-based servers always set one.
+    fn of(diagnostic: &Value) -> Self {
+        match diagnostic.get("severity").and_then(Value::as_u64) {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A `rules` entry as written in the settings.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSettings {
+    #[serde(default, deserialize_with = "deserialize_codes")]
+    pub codes: Option<Vec<u32>>,
+    #[serde(default)]
+    pub severities: Option<Vec<Severity>>,
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
+    /// Globs matched against the path relative to the workspace root
+    #[serde(default)]
+    pub files: Option<Vec<String>>,
+    #[serde(default)]
+    pub translate: Option<bool>,
+    #[serde(default)]
+    pub mode: Option<TranslationMode>,
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+/// A compiled rule; every condition that is set must hold for it to apply.
+#[derive(Clone)]
+struct Rule {
+    codes: Option<HashSet<u32>>,
+    severities: Option<Vec<Severity>>,
+    sources: Option<Vec<String>>,
+    files: Option<GlobSet>,
+    translate: bool,
+    mode: Option<TranslationMode>,
+}
+
+impl Rule {
+    fn compile(settings: RuleSettings) -> Result<Self, globset::Error> {
+        let files = match settings.files {
+            Some(globs) => {
+                let mut builder = GlobSetBuilder::new();
+                for glob in &globs {
+                    builder.add(Glob::new(glob)?);
+                }
+                Some(builder.build()?)
+            }
+            None => None,
+        };
+        Ok(Self {
+            codes: settings.codes.map(|codes| codes.into_iter().collect()),
+            severities: settings.severities,
+            sources: settings.sources,
+            files,
+            translate: settings.translate.unwrap_or(true),
+            mode: settings.mode,
+        })
+    }
+
+    fn matches(&self, diagnostic: &Value, path: Option<&Path>) -> bool {
+        if let Some(codes) = &self.codes {
+            let code = diagnostic.get("code").and_then(Value::as_u64);
+            if !code.is_some_and(|code| codes.contains(&(code as u32))) {
+                return false;
+            }
+        }
+        if let Some(severities) = &self.severities {
+            if !severities.contains(&Severity::of(diagnostic)) {
+                return false;
+            }
+        }
+        if let Some(sources) = &self.sources {
+            let source = diagnostic.get("source").and_then(Value::as_str);
+            if !source.is_some_and(|source| sources.iter().any(|s| s == source)) {
+                return false;
+            }
+        }
+        if let Some(files) = &self.files {
+            if !path.is_some_and(|path| files.is_match(path)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Decides per diagnostic whether to translate it and in which mode.
+/// The first matching rule wins; without one, everything is translated.
+#[derive(Clone, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Compiles `rules`, reporting and skipping those with an invalid glob.
+    pub fn new(rules: Vec<RuleSettings>) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter_map(|settings| match Rule::compile(settings) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    eprintln!("ts-error-translator-proxy: ignoring rule: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// The mode to translate `diagnostic` in, or `None` to leave it as the server sent it.
+    ///
+    /// `path` is the document's path, relative to the workspace root when inside it.
+    pub fn decide(&self, diagnostic: &Value, path: Option<&Path>, default: TranslationMode) -> Option<TranslationMode> {
+        match self.rules.iter().find(|rule| rule.matches(diagnostic, path)) {
+            Some(rule) if !rule.translate => None,
+            Some(rule) => Some(rule.mode.unwrap_or(default)),
+            None => Some(default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(rules: Value) -> Policy {
+        Policy::new(serde_json::from_value(rules).unwrap())
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let policy = policy(json!([
+            {"codes": ["TS2322"], "translate": false},
+            {"severities": ["warning", "hint"], "translate": false},
+            {"mode": "append"}
+        ]));
+
+        let decide = |diagnostic: Value| policy.decide(&diagnostic, None, TranslationMode::Replace);
+        assert!(decide(json!({"code": 2322, "severity": 1})).is_none());
+        assert!(decide(json!({"code": 7006, "severity": 2})).is_none());
+        assert!(matches!(decide(json!({"code": 7006, "severity": 1})), Some(TranslationMode::Append)));
+        assert!(matches!(decide(json!({"code": 7006})), Some(TranslationMode::Append)));
+    }
+
+    #[test]
+    fn test_source_and_files() {
+        let policy = policy(json!([
+            {"sources": ["eslint"], "translate": false},
+            {"files": ["**/*.test.ts"], "mode": "append"}
+        ]));
+
+        let diagnostic = json!({"code": 2322, "source": "ts"});
+        let test_file = Path::new("src/app.test.ts");
+        assert!(matches!(
+            policy.decide(&diagnostic, Some(test_file), TranslationMode::Replace),
+            Some(TranslationMode::Append)
+        ));
+        assert!(matches!(
+            policy.decide(&diagnostic, Some(Path::new("src/app.ts")), TranslationMode::Replace),
+            Some(TranslationMode::Replace)
+        ));
+        assert!(policy
+            .decide(&json!({"code": 2322, "source": "eslint"}), Some(test_file), TranslationMode::Replace)
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_glob_skips_rule() {
+        let policy = policy(json!([{"files": ["src/[unclosed"], "translate": false}]));
+        assert!(policy.decide(&json!({"code": 2322}), None, TranslationMode::Replace).is_some());
+    }
+}
@@ -1,43 +1,173 @@
-use crate::jsonrpc::{read_message, write_message};
-use crate::translator::translate_message;
+use crate::commands::{advertise_command, explain_actions, explanation_markdown, EXPLAIN_COMMAND};
+use crate::config::{load_project_settings, parse_settings, take_settings, Config, Settings, SETTINGS_KEY};
+use crate::errors::{Catalog, CatalogLayer};
+use crate::jsonrpc::{read_message, write_message, MessageError};
+use crate::store::{DiagnosticStore, StoredDiagnostic};
+use crate::supervisor::{Backoff, Connect, Lifecycle, Session};
+use crate::trace::{Direction, Trace};
+use crate::translator::{explain_message, translate_message, Style};
+use crate::workspace::{path_to_uri, private_dir, root_from_initialize, uri_to_path, workspace_catalog_path};
 use crate::TranslationMode;
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
 use tokio::io::{AsyncRead, AsyncWrite, BufReader};
 
-pub async fn run_proxy<R1, W1, R2, W2>(
+/// Prefix of ids for requests the proxy itself sends to the editor.
+const OWN_REQUEST_PREFIX: &str = "ts-error-translator/";
+
+/// `source` of the diagnostics the proxy adds in `diagnostic` mode.
+const TRANSLATION_SOURCE: &str = "ts-error-translator";
+
+/// Badly framed messages in a row after which a stream is taken to be out of step for good.
+const MAX_FRAMING_ERRORS: u32 = 8;
+
+/// Proxies between the editor and the server `connect` reaches, restarting
+/// the server when it exits without being asked to.
+///
+/// Returns once the editor closes its end or sends `exit`, the server exits
+/// after `shutdown`, or restarting the server failed too often. Badly framed
+/// messages from either side are skipped; an editor whose messages stay out of
+/// step ends the session with an error. Every message read or written is
+/// recorded to `trace`.
+pub async fn run_proxy<R1, W1, S>(
     editor_reader: R1,
-    mut editor_writer: W1,
-    lsp_reader: R2,
-    mut lsp_writer: W2,
-    mode: TranslationMode,
+    editor_writer: W1,
+    server: &mut S,
+    catalog: Catalog,
+    config: Config,
+    trace: &Trace,
 ) -> std::io::Result<()>
 where
-    R1: AsyncRead + Unpin + Send + 'static,
-    W1: AsyncWrite + Unpin + Send + 'static,
-    R2: AsyncRead + Unpin + Send + 'static,
-    W2: AsyncWrite + Unpin + Send + 'static,
+    R1: AsyncRead + Unpin,
+    W1: AsyncWrite + Unpin,
+    S: Connect,
 {
     let mut editor_reader = BufReader::new(editor_reader);
-    let mut lsp_reader = BufReader::new(lsp_reader);
+    let (lsp_reader, lsp_writer) = server.connect().await?;
+    let max_message_size = config.max_message_size;
+
+    let state = Mutex::new(ProxyState::new(catalog, config));
+    // Both directions write to the editor: server traffic and the proxy's own replies.
+    let editor_writer = tokio::sync::Mutex::new(editor_writer);
+    // Swapped for the new server's on restart.
+    let lsp_writer = tokio::sync::Mutex::new(lsp_writer);
 
     let editor_to_lsp = async {
+        // Badly framed editor messages are skipped like the server's. With
+        // nothing to restart, a stream that stays out of step ends the session.
+        let mut framing = Framing::default();
         loop {
-            let Some(msg) = read_message(&mut editor_reader).await? else {
-                break;
+            let (msg, problem) = match read_message(&mut editor_reader, max_message_size).await {
+                Ok(Some(msg)) => {
+                    trace.record(Direction::EditorToProxy, &msg);
+                    let problem = not_json(&msg);
+                    (msg, problem)
+                }
+                Ok(None) => break,
+                Err(e) if e.is_recoverable() => {
+                    eprintln!("ts-error-translator-proxy: skipping message from the editor: {}", e);
+                    let (to_editor, to_server) = state.lock().unwrap().skipped_message(true, &e);
+                    send_to_server(&lsp_writer, &to_server, trace).await;
+                    send_to_editor(&editor_writer, &to_editor, trace).await?;
+                    continue;
+                }
+                Err(e) if e.is_framing() => (Vec::new(), Some(e.to_string())),
+                Err(e) => return Err(e.into()),
+            };
+            if let Some(problem) = problem {
+                if framing.skip("the editor", &problem, &editor_writer, trace).await? {
+                    continue;
+                }
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "the editor's messages stay out of step",
+                ));
+            }
+            framing.reset();
+            // Handled under the server's lock, so a restart never replays a
+            // change that is still to be forwarded.
+            let mut lsp_writer = lsp_writer.lock().await;
+            let (route, outgoing) = {
+                let mut state = state.lock().unwrap();
+                let route = state.handle_editor_message(&msg);
+                (route, state.take_outgoing())
+            };
+            let replies = match route {
+                Route::Forward(forwarded) => {
+                    trace.record(Direction::ProxyToServer, &forwarded);
+                    // A server that just exited is noticed and restarted on the other side.
+                    if let Err(e) = write_message(&mut *lsp_writer, forwarded.as_ref()).await {
+                        eprintln!("ts-error-translator-proxy: cannot write to the language server: {}", e);
+                    }
+                    outgoing
+                }
+                Route::Respond(replies) => replies.into_iter().chain(outgoing).collect(),
             };
-            write_message(&mut lsp_writer, &msg).await?;
+            drop(lsp_writer);
+            send_to_editor(&editor_writer, &replies, trace).await?;
+            if state.lock().unwrap().session.lifecycle() == Lifecycle::Exited {
+                break;
+            }
         }
         Ok::<_, std::io::Error>(())
     };
 
     let lsp_to_editor = async {
+        let mut lsp_reader = BufReader::new(lsp_reader);
+        let mut started = Instant::now();
+        let mut backoff = Backoff::default();
+        let mut framing = Framing::default();
         loop {
-            let Some(msg) = read_message(&mut lsp_reader).await? else {
+            let problem = match read_message(&mut lsp_reader, max_message_size).await {
+                Ok(Some(msg)) => {
+                    trace.record(Direction::ServerToProxy, &msg);
+                    let Some(problem) = not_json(&msg) else {
+                        framing.reset();
+                        forward_to_editor(&state, &editor_writer, &msg, trace).await?;
+                        continue;
+                    };
+                    Some(problem)
+                }
+                Ok(None) => None,
+                Err(e) if e.is_recoverable() => {
+                    eprintln!("ts-error-translator-proxy: skipping message from the language server: {}", e);
+                    let (to_editor, to_server) = state.lock().unwrap().skipped_message(false, &e);
+                    send_to_server(&lsp_writer, &to_server, trace).await;
+                    send_to_editor(&editor_writer, &to_editor, trace).await?;
+                    continue;
+                }
+                Err(e) if e.is_framing() => Some(e.to_string()),
+                Err(e) => {
+                    eprintln!("ts-error-translator-proxy: cannot read from the language server: {}", e);
+                    None
+                }
+            };
+            // The server is still there; read past the bad bytes rather than
+            // restart it, unless reading does not get back in step.
+            if let Some(problem) = problem {
+                if framing.skip("the language server", &problem, &editor_writer, trace).await? {
+                    continue;
+                }
+                eprintln!("ts-error-translator-proxy: the language server's output stays out of step; restarting it");
+            }
+            if state.lock().unwrap().session.lifecycle() != Lifecycle::Running {
+                break;
+            }
+            let mut lsp_writer = lsp_writer.lock().await;
+            let failed = state.lock().unwrap().server_exited();
+            send_to_editor(&editor_writer, &failed, trace).await?;
+            let uptime = started.elapsed();
+            let Some(reader) = restart(server, &mut *lsp_writer, &state, &editor_writer, &mut backoff, uptime, trace).await?
+            else {
                 break;
             };
-            let transformed = transform_if_diagnostics(&msg, mode);
-            write_message(&mut editor_writer, transformed.as_ref()).await?;
+            lsp_reader = reader;
+            started = Instant::now();
+            framing.reset();
         }
         Ok::<_, std::io::Error>(())
     };
@@ -50,45 +180,1047 @@ where
     Ok(())
 }
 
-fn is_publish_diagnostics(json: &Value) -> bool {
-    json.get("method").and_then(Value::as_str) == Some("textDocument/publishDiagnostics")
+/// Counts badly framed messages from one side of the session, which are
+/// skipped and reported to the editor once per run of them.
+#[derive(Default)]
+struct Framing {
+    errors: u32,
+}
+
+impl Framing {
+    fn reset(&mut self) {
+        self.errors = 0;
+    }
+
+    /// Notes a skipped message. `false` once there have been
+    /// `MAX_FRAMING_ERRORS` in a row, and reading is not getting back in step.
+    async fn skip<W: AsyncWrite + Unpin>(
+        &mut self,
+        from: &str,
+        problem: &str,
+        editor_writer: &tokio::sync::Mutex<W>,
+        trace: &Trace,
+    ) -> std::io::Result<bool> {
+        eprintln!("ts-error-translator-proxy: skipping badly framed data from {}: {}", from, problem);
+        self.errors += 1;
+        if self.errors == 1 {
+            let message = format!("ts-error-translator-proxy skipped badly framed data from {}: {}", from, problem);
+            send_to_editor(editor_writer, &[log_message(2, &message)], trace).await?;
+        }
+        Ok(self.errors < MAX_FRAMING_ERRORS)
+    }
+}
+
+/// Why `body` cannot be a message, when it is not JSON at all, as happens
+/// to the start of a body that was longer than its Content-Length.
+fn not_json(body: &[u8]) -> Option<String> {
+    serde_json::from_slice::<serde::de::IgnoredAny>(body)
+        .err()
+        .map(|e| format!("message is not JSON: {}", e))
+}
+
+/// Starts a new server once the backoff allows, and replays the session to it.
+/// `None` once the server keeps exiting.
+async fn restart<S, W1>(
+    server: &mut S,
+    lsp_writer: &mut S::Writer,
+    state: &Mutex<ProxyState>,
+    editor_writer: &tokio::sync::Mutex<W1>,
+    backoff: &mut Backoff,
+    mut uptime: std::time::Duration,
+    trace: &Trace,
+) -> std::io::Result<Option<BufReader<S::Reader>>>
+where
+    S: Connect,
+    W1: AsyncWrite + Unpin,
+{
+    loop {
+        let Some(delay) = backoff.next_delay(uptime) else {
+            let message = show_message(1, "The language server keeps exiting; giving up on restarting it.");
+            send_to_editor(editor_writer, &[message], trace).await?;
+            return Ok(None);
+        };
+        tokio::time::sleep(delay).await;
+        uptime = std::time::Duration::ZERO;
+
+        let (reader, writer) = match server.connect().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("ts-error-translator-proxy: cannot restart the language server: {}", e);
+                continue;
+            }
+        };
+        *lsp_writer = writer;
+        let mut reader = BufReader::new(reader);
+        match replay(&mut reader, lsp_writer, state, editor_writer, trace).await {
+            Ok(()) => return Ok(Some(reader)),
+            Err(e) => eprintln!("ts-error-translator-proxy: restarted language server failed: {}", e),
+        }
+    }
 }
 
-fn transform_if_diagnostics(msg: &[u8], mode: TranslationMode) -> Cow<'_, [u8]> {
-    let Ok(mut json) = serde_json::from_slice::<Value>(msg) else {
-        return Cow::Borrowed(msg);
+/// Sends the recorded `initialize` to a new server, waits for its answer
+/// (which the editor already had from the first server), then reopens the documents.
+async fn replay<R, W, W1>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    state: &Mutex<ProxyState>,
+    editor_writer: &tokio::sync::Mutex<W1>,
+    trace: &Trace,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    W1: AsyncWrite + Unpin,
+{
+    let Some((id, initialize)) = state.lock().unwrap().replay_initialize() else {
+        return Ok(());
     };
+    let max_message_size = state.lock().unwrap().config.max_message_size;
+    trace.record(Direction::ProxyToServer, &initialize);
+    write_message(writer, &initialize).await?;
+    loop {
+        let Some(msg) = read_message(reader, max_message_size).await? else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "exited before answering initialize",
+            ));
+        };
+        trace.record(Direction::ServerToProxy, &msg);
+        let response = serde_json::from_slice::<Value>(&msg)
+            .ok()
+            .filter(|response| response.get("method").is_none() && response.get("id") == Some(&id));
+        match response {
+            Some(response) if response.get("error").is_some() => {
+                return Err(std::io::Error::other(format!("initialize failed: {}", response["error"])));
+            }
+            Some(_) => break,
+            None => forward_to_editor(state, editor_writer, &msg, trace).await?,
+        }
+    }
 
-    if !is_publish_diagnostics(&json) {
-        return Cow::Borrowed(msg);
+    let reopen = state.lock().unwrap().session.reopen();
+    for message in reopen {
+        let message = serde_json::to_vec(&message)?;
+        trace.record(Direction::ProxyToServer, &message);
+        write_message(writer, &message).await?;
     }
+    Ok(())
+}
+
+async fn forward_to_editor<W: AsyncWrite + Unpin>(
+    state: &Mutex<ProxyState>,
+    editor_writer: &tokio::sync::Mutex<W>,
+    msg: &[u8],
+    trace: &Trace,
+) -> std::io::Result<()> {
+    let transformed = state.lock().unwrap().transform_if_diagnostics(msg);
+    trace.record(Direction::ProxyToEditor, &transformed);
+    write_message(&mut *editor_writer.lock().await, transformed.as_ref()).await
+}
+
+async fn send_to_editor<W: AsyncWrite + Unpin>(
+    editor_writer: &tokio::sync::Mutex<W>,
+    messages: &[Vec<u8>],
+    trace: &Trace,
+) -> std::io::Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let mut editor_writer = editor_writer.lock().await;
+    for message in messages {
+        trace.record(Direction::ProxyToEditor, message);
+        write_message(&mut *editor_writer, message).await?;
+    }
+    Ok(())
+}
 
-    let Some(diagnostics) = json
-        .get_mut("params")
-        .and_then(|p| p.get_mut("diagnostics"))
+/// Our own messages for the server. A failed write is only reported: the
+/// server's exit is noticed, and handled, on the reading side.
+async fn send_to_server<W: AsyncWrite + Unpin>(lsp_writer: &tokio::sync::Mutex<W>, messages: &[Vec<u8>], trace: &Trace) {
+    if messages.is_empty() {
+        return;
+    }
+    let mut lsp_writer = lsp_writer.lock().await;
+    for message in messages {
+        trace.record(Direction::ProxyToServer, message);
+        if let Err(e) = write_message(&mut *lsp_writer, message).await {
+            eprintln!("ts-error-translator-proxy: cannot write to the language server: {}", e);
+            return;
+        }
+    }
+}
+
+/// A JSON-RPC error response.
+fn error_response(id: &Value, code: i64, message: &str) -> Vec<u8> {
+    let response = json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}});
+    serde_json::to_vec(&response).unwrap_or_default()
+}
+
+/// A `window/logMessage` notification of the given `MessageType`.
+fn log_message(kind: u8, message: &str) -> Vec<u8> {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "window/logMessage",
+        "params": {"type": kind, "message": message}
+    });
+    serde_json::to_vec(&notification).unwrap_or_default()
+}
+
+/// A `window/showMessage` notification of the given `MessageType`.
+fn show_message(kind: u8, message: &str) -> Vec<u8> {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "window/showMessage",
+        "params": {"type": kind, "message": message}
+    });
+    serde_json::to_vec(&notification).unwrap_or_default()
+}
+
+/// Runs messages from a trace through the proxy's handling, without an editor or server.
+pub struct Replayer(ProxyState);
+
+impl Replayer {
+    pub fn new(catalog: Catalog, config: Config) -> Self {
+        Self(ProxyState::new(catalog, config))
+    }
+
+    /// Handles a message from the editor; nothing is sent anywhere.
+    pub fn editor_message(&mut self, msg: &[u8]) {
+        self.0.handle_editor_message(msg);
+        self.0.take_outgoing();
+    }
+
+    /// What the editor gets for a message from the server.
+    pub fn server_message(&mut self, msg: &[u8]) -> Vec<u8> {
+        self.0.transform_if_diagnostics(msg).into_owned()
+    }
+}
+
+/// What to do with a message from the editor.
+enum Route<'a> {
+    /// Pass it on to the server, possibly rewritten
+    Forward(Cow<'a, [u8]>),
+    /// Handled by the proxy: send these messages back to the editor instead
+    Respond(Vec<Vec<u8>>),
+}
+
+/// Editor requests whose responses the proxy rewrites.
+#[derive(Clone)]
+enum PendingRequest {
+    Initialize,
+    DocumentDiagnostic { uri: String },
+    WorkspaceDiagnostic,
+    Hover { uri: String, position: Value },
+    CodeAction {
+        /// Diagnostics as the editor sent them
+        diagnostics: Vec<Value>,
+        /// The same diagnostics as the server published them
+        originals: Vec<Value>,
+        only: Option<Vec<Value>>,
+    },
+}
+
+/// Requests the proxy sends to the editor, whose responses it consumes.
+enum OwnRequest {
+    ShowDocument,
+    Configuration,
+}
+
+struct ProxyState {
+    /// Built-in, command-line and user catalogs
+    base: Catalog,
+    /// `base` plus the configured and workspace catalogs, minus disabled codes
+    catalog: Catalog,
+    /// Command-line options, the project config file and `initializationOptions`;
+    /// later settings apply on top
+    startup: Config,
+    config: Config,
+    /// Workspace root from `initialize`
+    root: Option<PathBuf>,
+    /// The editor's locale from `initialize`, which servers pass on to tsserver
+    client_locale: Option<String>,
+    diagnostics: DiagnosticStore,
+    /// Outstanding editor requests, keyed by serialized request id
+    pending: HashMap<String, PendingRequest>,
+    /// Pull-diagnostic requests streaming partial results, keyed by serialized token
+    partial_results: HashMap<String, PendingRequest>,
+    /// Serialized `partialResultToken` of each request in `partial_results`, keyed by request id
+    partial_tokens: HashMap<String, String>,
+    /// Whether the editor supports `window/showDocument`
+    show_document: bool,
+    /// Whether the editor answers `workspace/configuration` requests
+    pull_configuration: bool,
+    /// Whether the editor supports `codeDescription` on diagnostics
+    code_description: bool,
+    /// Counter for ids of requests the proxy sends to the editor
+    next_request_id: u64,
+    /// Our requests awaiting the editor's response, keyed by id
+    own_requests: HashMap<String, OwnRequest>,
+    /// Messages for the editor produced while handling an editor message
+    outgoing: Vec<Vec<u8>>,
+    /// What a restarted server needs to be told
+    session: Session,
+    /// Restarts so far, numbering the replayed `initialize` requests
+    restarts: u64,
+    /// Unknown settings keys the user has been warned about
+    reported_settings: HashSet<String>,
+}
+
+impl ProxyState {
+    fn new(catalog: Catalog, config: Config) -> Self {
+        let mut state = Self {
+            base: catalog.clone(),
+            catalog,
+            startup: config.clone(),
+            config,
+            root: None,
+            client_locale: None,
+            diagnostics: DiagnosticStore::default(),
+            pending: HashMap::new(),
+            partial_results: HashMap::new(),
+            partial_tokens: HashMap::new(),
+            show_document: false,
+            pull_configuration: false,
+            code_description: false,
+            next_request_id: 0,
+            own_requests: HashMap::new(),
+            outgoing: Vec::new(),
+            session: Session::default(),
+            restarts: 0,
+            reported_settings: HashSet::new(),
+        };
+        state.rebuild_catalog();
+        state
+    }
+
+    fn take_outgoing(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn handle_editor_message<'a>(&mut self, msg: &'a [u8]) -> Route<'a> {
+        let Ok(mut json) = serde_json::from_slice::<Value>(msg) else {
+            return Route::Forward(Cow::Borrowed(msg));
+        };
+        let Some(method) = json.get("method").and_then(Value::as_str).map(str::to_string) else {
+            // The editor's answer to one of our own requests is not the server's business.
+            let Some(id) = json
+                .get("id")
+                .and_then(Value::as_str)
+                .filter(|id| id.starts_with(OWN_REQUEST_PREFIX))
+            else {
+                return Route::Forward(Cow::Borrowed(msg));
+            };
+            if let Some(OwnRequest::Configuration) = self.own_requests.remove(id) {
+                let section = json.pointer("/result/0").cloned().unwrap_or(Value::Null);
+                if let Some(settings) = parse_settings(section) {
+                    self.report_unknown_settings(&settings);
+                    let mut config = self.startup.clone();
+                    config.merge(settings);
+                    self.set_config(config);
+                }
+            }
+            return Route::Respond(Vec::new());
+        };
+
+        let changed = match method.as_str() {
+            "initialize" => {
+                self.root = json.get("params").and_then(root_from_initialize);
+                self.client_locale = json.pointer("/params/locale").and_then(Value::as_str).map(str::to_string);
+                if let Some(settings) = self.root.as_deref().and_then(load_project_settings) {
+                    self.report_unknown_settings(&settings);
+                    self.startup.merge(settings);
+                }
+                self.show_document = json
+                    .pointer("/params/capabilities/window/showDocument/support")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                self.pull_configuration = json
+                    .pointer("/params/capabilities/workspace/configuration")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                self.code_description = json
+                    .pointer("/params/capabilities/textDocument/publishDiagnostics/codeDescriptionSupport")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                let settings = json
+                    .pointer_mut("/params/initializationOptions")
+                    .map(|options| (options.get(SETTINGS_KEY).is_some(), take_settings(options)));
+                let stripped = matches!(settings, Some((true, _)));
+                if let Some((_, Some(settings))) = settings {
+                    self.report_unknown_settings(&settings);
+                    self.startup.merge(settings);
+                }
+                self.set_config(self.startup.clone());
+                self.track_request(&json, PendingRequest::Initialize);
+                stripped
+            }
+            "initialized" => {
+                self.request_configuration();
+                false
+            }
+            "workspace/didChangeConfiguration" => {
+                let section = json
+                    .pointer_mut("/params/settings")
+                    .map(|settings| (settings.get(SETTINGS_KEY).is_some(), take_settings(settings)));
+                match section {
+                    Some((true, settings)) => {
+                        let mut config = self.startup.clone();
+                        if let Some(settings) = settings {
+                            self.report_unknown_settings(&settings);
+                            config.merge(settings);
+                        }
+                        self.set_config(config);
+                        true
+                    }
+                    // Push-less clients send empty settings and expect a pull.
+                    _ => {
+                        self.request_configuration();
+                        false
+                    }
+                }
+            }
+            "workspace/executeCommand"
+                if json.pointer("/params/command").and_then(Value::as_str) == Some(EXPLAIN_COMMAND) =>
+            {
+                return Route::Respond(self.execute_explain(&json));
+            }
+            "textDocument/codeAction" => {
+                let dropped = drop_translation_diagnostics(&mut json);
+                let diagnostics = json
+                    .pointer("/params/context/diagnostics")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                let restored = restore_code_action_diagnostics(&mut json, &self.diagnostics);
+                let originals = json
+                    .pointer("/params/context/diagnostics")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                let only = json
+                    .pointer("/params/context/only")
+                    .and_then(Value::as_array)
+                    .cloned();
+                self.track_request(
+                    &json,
+                    PendingRequest::CodeAction {
+                        diagnostics,
+                        originals,
+                        only,
+                    },
+                );
+                dropped | restored
+            }
+            "textDocument/diagnostic" => {
+                let uri = json
+                    .pointer("/params/textDocument/uri")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                self.track_request(&json, PendingRequest::DocumentDiagnostic { uri });
+                false
+            }
+            "workspace/diagnostic" => {
+                self.track_request(&json, PendingRequest::WorkspaceDiagnostic);
+                false
+            }
+            "$/cancelRequest" => {
+                if let Some(id) = json.pointer("/params/id") {
+                    self.finish_request(&id.to_string());
+                }
+                false
+            }
+            "textDocument/hover" => {
+                let uri = json.pointer("/params/textDocument/uri").and_then(Value::as_str);
+                let position = json.pointer("/params/position");
+                if let (Some(uri), Some(position)) = (uri, position) {
+                    let pending = PendingRequest::Hover {
+                        uri: uri.to_string(),
+                        position: position.clone(),
+                    };
+                    self.track_request(&json, pending);
+                }
+                false
+            }
+            _ => false,
+        };
+
+        self.session.record_editor_message(&json);
+        if !changed {
+            return Route::Forward(Cow::Borrowed(msg));
+        }
+        Route::Forward(Cow::Owned(serde_json::to_vec(&json).unwrap_or_else(|_| msg.to_vec())))
+    }
+
+    /// Fails the requests the exited server left unanswered, forgets what the
+    /// proxy tracked for them and tells the user a restart is under way.
+    fn server_exited(&mut self) -> Vec<Vec<u8>> {
+        let mut messages: Vec<Vec<u8>> = self
+            .session
+            .fail_in_flight()
+            .iter()
+            .filter_map(|response| serde_json::to_vec(response).ok())
+            .collect();
+        self.pending.clear();
+        self.partial_results.clear();
+        self.partial_tokens.clear();
+        messages.push(show_message(
+            2,
+            "The language server exited unexpectedly; restarting it and reopening your documents.",
+        ));
+        messages
+    }
+
+    /// The recorded `initialize` under a fresh id of our own, and that id.
+    fn replay_initialize(&mut self) -> Option<(Value, Vec<u8>)> {
+        self.restarts += 1;
+        let id = format!("{}restart-{}", OWN_REQUEST_PREFIX, self.restarts);
+        let request = self.session.initialize_request(&id)?;
+        Some((Value::String(id), serde_json::to_vec(&request).ok()?))
+    }
+
+    /// Answers our explain command and shows the explanation, as a document
+    /// when the editor supports it and as a message otherwise.
+    fn execute_explain(&mut self, request: &Value) -> Vec<Vec<u8>> {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let arguments = request.pointer("/params/arguments").cloned().unwrap_or(Value::Null);
+
+        let Some((code, markdown)) = explanation_markdown(&self.catalog, &arguments, &self.config.style) else {
+            let error = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": -32602, "message": "No explanation available for these arguments"}
+            });
+            return vec![serde_json::to_vec(&error).unwrap_or_default()];
+        };
+
+        let response = json!({"jsonrpc": "2.0", "id": id, "result": null});
+        let show = match self.show_document.then(|| write_explanation(code, &markdown)).flatten() {
+            Some(uri) => {
+                let id = self.own_request_id(OwnRequest::ShowDocument);
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "window/showDocument",
+                    "params": {"uri": uri, "takeFocus": true}
+                })
+            }
+            None => json!({
+                "jsonrpc": "2.0",
+                "method": "window/showMessage",
+                "params": {"type": 3, "message": markdown}
+            }),
+        };
+
+        [response, show]
+            .iter()
+            .filter_map(|m| serde_json::to_vec(m).ok())
+            .collect()
+    }
+
+    fn own_request_id(&mut self, request: OwnRequest) -> String {
+        self.next_request_id += 1;
+        let id = format!("{}{}", OWN_REQUEST_PREFIX, self.next_request_id);
+        self.own_requests.insert(id.clone(), request);
+        id
+    }
+
+    /// Asks the editor for our settings section, if it supports `workspace/configuration`.
+    fn request_configuration(&mut self) {
+        if !self.pull_configuration {
+            return;
+        }
+        let id = self.own_request_id(OwnRequest::Configuration);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "workspace/configuration",
+            "params": {"items": [{"section": SETTINGS_KEY}]}
+        });
+        self.outgoing.extend(serde_json::to_vec(&request).ok());
+    }
+
+    /// Warns the user once about each settings key the proxy does not know.
+    fn report_unknown_settings(&mut self, settings: &Settings) {
+        let keys: Vec<String> = settings
+            .unknown_keys()
+            .into_iter()
+            .filter(|key| self.reported_settings.insert(key.clone()))
+            .collect();
+        if !keys.is_empty() {
+            let message = format!("Ignoring unknown {} settings: {}", SETTINGS_KEY, keys.join(", "));
+            eprintln!("ts-error-translator-proxy: {}", message);
+            self.outgoing.push(show_message(2, &message));
+        }
+    }
+
+    fn set_config(&mut self, config: Config) {
+        self.config = config;
+        self.rebuild_catalog();
+    }
+
+    fn rebuild_catalog(&mut self) {
+        self.catalog = session_catalog(&self.base, &self.config, self.root.as_deref(), self.client_locale.as_deref());
+    }
+
+    fn track_request(&mut self, request: &Value, pending: PendingRequest) {
+        let Some(id) = request.get("id").map(Value::to_string) else {
+            return;
+        };
+        if let Some(token) = request.pointer("/params/partialResultToken").map(Value::to_string) {
+            self.partial_results.insert(token.clone(), pending.clone());
+            self.partial_tokens.insert(id.clone(), token);
+        }
+        self.pending.insert(id, pending);
+    }
+
+    /// Forgets a request once it is answered, failed or cancelled, along with its partial results.
+    fn finish_request(&mut self, id: &str) -> Option<PendingRequest> {
+        if let Some(token) = self.partial_tokens.remove(id) {
+            self.partial_results.remove(&token);
+        }
+        self.pending.remove(id)
+    }
+
+    /// What to send, to the editor and to the server, for a message that was
+    /// skipped unread, so that nobody waits forever on it: an error answering
+    /// a skipped request, an error in place of a skipped response, and a
+    /// logged warning for anything else.
+    fn skipped_message(&mut self, from_editor: bool, error: &MessageError) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let (mut to_editor, mut to_server) = (Vec::new(), Vec::new());
+        let Some(skipped) = error.skipped() else {
+            return (to_editor, to_server);
+        };
+        let own = |id: &Value| id.as_str().is_some_and(|id| id.starts_with(OWN_REQUEST_PREFIX));
+        match &skipped.id {
+            // -32600: InvalidRequest
+            Some(id) if skipped.is_request() => {
+                let reply = error_response(id, -32600, &format!("Message skipped by ts-error-translator-proxy: {}", error));
+                if from_editor { to_editor.push(reply) } else { to_server.push(reply) }
+            }
+            Some(id) if own(id) => {
+                self.own_requests.remove(id.as_str().unwrap_or_default());
+            }
+            // -32603: InternalError, standing in for the answer that never arrives
+            Some(id) => {
+                let reply = error_response(id, -32603, &format!("Response skipped by ts-error-translator-proxy: {}", error));
+                if from_editor {
+                    to_server.push(reply);
+                } else {
+                    self.finish_request(&id.to_string());
+                    to_editor.push(reply);
+                }
+            }
+            None => {
+                let from = if from_editor { "the editor" } else { "the language server" };
+                to_editor.push(log_message(2, &format!("ts-error-translator-proxy skipped a message from {}: {}", from, error)));
+            }
+        }
+        (to_editor, to_server)
+    }
+
+    /// Translates server messages that carry diagnostics: `publishDiagnostics`
+    /// notifications, pull diagnostic responses and their partial results.
+    fn transform_if_diagnostics<'a>(&mut self, msg: &'a [u8]) -> Cow<'a, [u8]> {
+        let Ok(mut json) = serde_json::from_slice::<Value>(msg) else {
+            return Cow::Borrowed(msg);
+        };
+        self.session.record_server_message(&json);
+
+        let changed = match json.get("method").and_then(Value::as_str) {
+            Some("textDocument/publishDiagnostics") => match json.get_mut("params") {
+                Some(params) => self.transform_publish(params),
+                None => false,
+            },
+            Some("$/progress") => {
+                let pending = json
+                    .pointer("/params/token")
+                    .and_then(|token| self.partial_results.get(&token.to_string()))
+                    .cloned();
+                match (pending, json.pointer_mut("/params/value")) {
+                    (Some(pending), Some(value)) => self.transform_report(&pending, value),
+                    _ => false,
+                }
+            }
+            Some(_) => false,
+            None => {
+                let pending = json.get("id").and_then(|id| self.finish_request(&id.to_string()));
+                match (pending, json.get_mut("result")) {
+                    (Some(PendingRequest::Initialize), Some(result)) => advertise_command(result),
+                    (Some(PendingRequest::Hover { uri, position }), Some(result)) if self.config.enabled => {
+                        self.augment_hover(&uri, &position, result)
+                    }
+                    (
+                        Some(PendingRequest::CodeAction {
+                            diagnostics,
+                            originals,
+                            only,
+                        }),
+                        Some(result),
+                    ) if self.config.enabled => {
+                        let actions = explain_actions(&self.catalog, &diagnostics, &originals, only.as_deref());
+                        append_code_actions(result, actions)
+                    }
+                    (Some(pending), Some(result)) => self.transform_report(&pending, result),
+                    _ => false,
+                }
+            }
+        };
+
+        if !changed {
+            return Cow::Borrowed(msg);
+        }
+        Cow::Owned(serde_json::to_vec(&json).unwrap_or_else(|_| msg.to_vec()))
+    }
+
+    fn transform_publish(&mut self, params: &mut Value) -> bool {
+        let uri = params.get("uri").and_then(Value::as_str).unwrap_or_default().to_string();
+        match params.get_mut("diagnostics").and_then(Value::as_array_mut) {
+            Some(diagnostics) => self.transform_diagnostics(&uri, diagnostics),
+            None => false,
+        }
+    }
+
+    fn transform_report(&mut self, pending: &PendingRequest, result: &mut Value) -> bool {
+        match pending {
+            PendingRequest::DocumentDiagnostic { uri } => {
+                let mut changed = self.transform_document_report(uri, result);
+                if let Some(related) = result.get_mut("relatedDocuments").and_then(Value::as_object_mut) {
+                    for (uri, report) in related {
+                        changed |= self.transform_document_report(uri, report);
+                    }
+                }
+                changed
+            }
+            PendingRequest::WorkspaceDiagnostic => {
+                let mut changed = false;
+                if let Some(reports) = result.get_mut("items").and_then(Value::as_array_mut) {
+                    for report in reports {
+                        let uri = report.get("uri").and_then(Value::as_str).unwrap_or_default().to_string();
+                        changed |= self.transform_document_report(&uri, report);
+                    }
+                }
+                changed
+            }
+            PendingRequest::Initialize | PendingRequest::Hover { .. } | PendingRequest::CodeAction { .. } => false,
+        }
+    }
+
+    /// Appends the long explanation of every translated diagnostic under the cursor.
+    fn augment_hover(&self, uri: &str, position: &Value, result: &mut Value) -> bool {
+        let mut range = None;
+        let explanations: Vec<String> = self
+            .diagnostics
+            .at_position(uri, position)
+            .filter_map(|stored| {
+                let code = stored.original.get("code").and_then(Value::as_u64)? as u32;
+                let message = stored.original.get("message").and_then(Value::as_str)?;
+                let body = explain_message(&self.catalog, message, code)?;
+                range.get_or_insert_with(|| stored.original.get("range").cloned());
+                Some(format!("**TS{}**\n\n{}", code, body))
+            })
+            .collect();
+
+        if explanations.is_empty() {
+            return false;
+        }
+        append_hover_markdown(result, &explanations.join("\n\n---\n\n"), range.flatten());
+        true
+    }
+
+    /// Translates a full document report; `unchanged` reports carry no items.
+    fn transform_document_report(&mut self, uri: &str, report: &mut Value) -> bool {
+        match report.get_mut("items").and_then(Value::as_array_mut) {
+            Some(items) => self.transform_diagnostics(uri, items),
+            None => false,
+        }
+    }
+
+    fn transform_diagnostics(&mut self, uri: &str, diagnostics: &mut Vec<Value>) -> bool {
+        if !self.config.enabled {
+            self.diagnostics.replace(uri, Vec::new());
+            return false;
+        }
+        let path = uri_to_path(uri).map(|path| match self.root.as_deref().and_then(|root| path.strip_prefix(root).ok()) {
+            Some(relative) => relative.to_path_buf(),
+            None => path,
+        });
+        let mut stored = Vec::new();
+        let mut linked = false;
+        let mut output = Vec::with_capacity(diagnostics.len());
+        for mut diagnostic in diagnostics.drain(..) {
+            let original = diagnostic.clone();
+            let extra = transform_diagnostic(&mut diagnostic, &self.catalog, &self.config, uri, path.as_deref());
+            let translated = diagnostic != original || extra.is_some();
+            // Known codes get their link even when the message is left as it is.
+            if self.code_description && add_code_description(&mut diagnostic, &self.catalog, &self.config.style) {
+                linked = true;
+            }
+            if translated {
+                stored.push(StoredDiagnostic {
+                    original,
+                    translated_message: diagnostic["message"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            output.push(diagnostic);
+            output.extend(extra);
+        }
+        *diagnostics = output;
+        let changed = linked || !stored.is_empty();
+        self.diagnostics.replace(uri, stored);
+        changed
+    }
+}
+
+/// Layers the configured catalogs and the workspace catalog at `root` over
+/// `base`, drops the codes the allow and deny lists rule out, and adds the
+/// patterns for the configured locale, or else the client's.
+pub fn session_catalog(base: &Catalog, config: &Config, root: Option<&Path>, client_locale: Option<&str>) -> Catalog {
+    let mut catalog = base.clone();
+    for path in &config.catalogs {
+        let path = match root {
+            Some(root) if path.is_relative() => root.join(path),
+            _ => path.clone(),
+        };
+        if let Err(e) = CatalogLayer::load(&path).and_then(|layer| catalog.apply(&layer)) {
+            eprintln!("ts-error-translator-proxy: ignoring configured catalog: {}", e);
+        }
+    }
+    if let Some(layered) = root.and_then(|root| layer_workspace_catalog(&catalog, root)) {
+        catalog = layered;
+    }
+    if let Some(allowed) = &config.enabled_codes {
+        catalog.retain(|code| allowed.contains(&code));
+    }
+    for code in &config.disabled_codes {
+        catalog.disable(*code);
+    }
+    match (&config.locale, client_locale) {
+        (Some(locale), _) if !catalog.localize(locale) => {
+            eprintln!("ts-error-translator-proxy: ignoring locale '{}': no TypeScript patterns for it", locale);
+        }
+        (Some(_), _) => {}
+        (None, Some(locale)) => {
+            catalog.localize(locale);
+        }
+        (None, None) => {}
+    }
+    catalog
+}
+
+fn layer_workspace_catalog(base: &Catalog, root: &Path) -> Option<Catalog> {
+    let path = workspace_catalog_path(root)?;
+    let mut catalog = base.clone();
+    match CatalogLayer::load(&path).and_then(|layer| catalog.apply(&layer)) {
+        Ok(()) => Some(catalog),
+        Err(e) => {
+            eprintln!("ts-error-translator-proxy: ignoring workspace catalog: {}", e);
+            None
+        }
+    }
+}
+
+fn append_code_actions(result: &mut Value, actions: Vec<Value>) -> bool {
+    if actions.is_empty() {
+        return false;
+    }
+    if result.is_null() {
+        *result = Value::Array(Vec::new());
+    }
+    match result.as_array_mut() {
+        Some(existing) => {
+            existing.extend(actions);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Writes an explanation to a markdown file in the user's private directory
+/// for `window/showDocument`.
+fn write_explanation(code: u32, markdown: &str) -> Option<String> {
+    use std::io::Write;
+
+    let dir = private_dir()?;
+    let path = dir.join(format!("TS{}.md", code));
+    // Written to a fresh file and renamed over the page, so that whatever is
+    // at `path` is replaced rather than followed.
+    let temp = dir.join(format!(".TS{}.{}.md", code, std::process::id()));
+    let _ = std::fs::remove_file(&temp);
+    let written = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| file.write_all(markdown.as_bytes()))
+        .and_then(|()| std::fs::rename(&temp, &path));
+    if let Err(e) = written {
+        eprintln!("ts-error-translator-proxy: cannot write explanation: {}", e);
+        let _ = std::fs::remove_file(&temp);
+        return None;
+    }
+    Some(path_to_uri(&path))
+}
+
+/// Adds `markdown` to a hover result, whichever of the LSP content shapes it uses.
+fn append_hover_markdown(result: &mut Value, markdown: &str, range: Option<Value>) {
+    if result.is_null() {
+        *result = json!({"contents": {"kind": "markdown", "value": markdown}});
+        if let Some(range) = range {
+            result["range"] = range;
+        }
+        return;
+    }
+
+    let Some(contents) = result.get_mut("contents") else {
+        return;
+    };
+    match contents {
+        // MarkupContent
+        Value::Object(markup) if markup.contains_key("kind") => {
+            let value = markup.get("value").and_then(Value::as_str).unwrap_or_default();
+            let joined = if value.is_empty() {
+                markdown.to_string()
+            } else {
+                format!("{}\n\n---\n\n{}", value, markdown)
+            };
+            markup.insert("value".to_string(), Value::String(joined));
+        }
+        // MarkedString[]
+        Value::Array(items) => items.push(Value::String(markdown.to_string())),
+        // A single MarkedString
+        other => {
+            let existing = other.take();
+            *other = json!([existing, markdown]);
+        }
+    }
+}
+
+/// Links a diagnostic whose code is in the catalog to its documentation,
+/// unless the server already did.
+fn add_code_description(diagnostic: &mut Value, catalog: &Catalog, style: &Style) -> bool {
+    if diagnostic.get("codeDescription").is_some() {
+        return false;
+    }
+    let Some(code) = diagnostic.get("code").and_then(Value::as_u64).map(|code| code as u32) else {
+        return false;
+    };
+    if catalog.get(code).is_none() {
+        return false;
+    }
+    let Some(href) = style.link_for(code) else {
+        return false;
+    };
+    diagnostic["codeDescription"] = json!({"href": href});
+    true
+}
+
+/// Removes the diagnostics added in `diagnostic` mode from a `textDocument/codeAction`
+/// request; the server has never seen them.
+fn drop_translation_diagnostics(request: &mut Value) -> bool {
+    let Some(diagnostics) = request
+        .pointer_mut("/params/context/diagnostics")
+        .and_then(Value::as_array_mut)
+    else {
+        return false;
+    };
+    let before = diagnostics.len();
+    diagnostics.retain(|d| d.get("source").and_then(Value::as_str) != Some(TRANSLATION_SOURCE));
+    diagnostics.len() != before
+}
+
+/// Puts the server's own diagnostics back into a `textDocument/codeAction`
+/// request, so quick fixes keyed on the original message still match.
+fn restore_code_action_diagnostics(request: &mut Value, store: &DiagnosticStore) -> bool {
+    let Some(params) = request.get_mut("params") else {
+        return false;
+    };
+    let Some(uri) = params
+        .pointer("/textDocument/uri")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+    else {
+        return false;
+    };
+    let Some(diagnostics) = params
+        .pointer_mut("/context/diagnostics")
         .and_then(Value::as_array_mut)
     else {
-        return Cow::Borrowed(msg);
+        return false;
     };
 
+    let mut restored_any = false;
     for diagnostic in diagnostics {
-        transform_diagnostic(diagnostic, mode);
+        if let Some(original) = store.original_for(&uri, diagnostic) {
+            *diagnostic = original.clone();
+            restored_any = true;
+        }
     }
-
-    Cow::Owned(serde_json::to_vec(&json).unwrap_or_else(|_| msg.to_vec()))
+    restored_any
 }
 
-fn transform_diagnostic(diagnostic: &mut Value, mode: TranslationMode) {
+/// Translates `diagnostic` in place, or for `diagnostic` mode returns the
+/// separate diagnostic carrying the translation.
+fn transform_diagnostic(
+    diagnostic: &mut Value,
+    catalog: &Catalog,
+    config: &Config,
+    uri: &str,
+    path: Option<&Path>,
+) -> Option<Value> {
+    let mode = config.policy.decide(diagnostic, path, config.mode)?;
     let code = diagnostic.get("code").and_then(Value::as_i64);
 
-    let Some(message) = diagnostic.get_mut("message") else {
-        return;
-    };
+    if !mode.rewrites_message() {
+        let message = diagnostic.get("message").and_then(Value::as_str)?;
+        let Cow::Owned(translation) = translate_message(catalog, message, code, mode, &config.style) else {
+            return None;
+        };
+        let range = diagnostic.get("range").cloned().unwrap_or(Value::Null);
+        if mode == TranslationMode::Diagnostic {
+            let mut extra = json!({
+                "range": range,
+                "severity": 3,
+                "source": TRANSLATION_SOURCE,
+                "message": translation
+            });
+            if let Some(code) = diagnostic.get("code") {
+                extra["code"] = code.clone();
+            }
+            return Some(extra);
+        }
+        let entry = json!({"location": {"uri": uri, "range": range}, "message": translation});
+        match diagnostic.get_mut("relatedInformation").and_then(Value::as_array_mut) {
+            Some(related) => related.insert(0, entry),
+            None => diagnostic["relatedInformation"] = json!([entry]),
+        }
+        return None;
+    }
+
+    if let Some(message) = diagnostic.get_mut("message") {
+        transform_message(message, catalog, code, mode, &config.style);
+    }
+
+    // Related entries have no code of their own; they are matched by TS prefix or pattern.
+    if let Some(related) = diagnostic
+        .get_mut("relatedInformation")
+        .and_then(Value::as_array_mut)
+    {
+        for info in related {
+            if let Some(message) = info.get_mut("message") {
+                transform_message(message, catalog, None, mode, &config.style);
+            }
+        }
+    }
+    None
+}
+
+fn transform_message(message: &mut Value, catalog: &Catalog, code: Option<i64>, mode: TranslationMode, style: &Style) {
     let Some(msg_str) = message.as_str() else {
         return;
     };
 
-    let translated = translate_message(msg_str, code, mode);
+    let translated = translate_message(catalog, msg_str, code, mode, style);
     if let Cow::Owned(s) = translated {
         *message = Value::String(s);
     }
@@ -97,7 +1229,8 @@ fn transform_diagnostic(diagnostic: &mut Value, mode: TranslationMode) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
+    use crate::errors::ERRORS;
+    use crate::jsonrpc::DEFAULT_MAX_MESSAGE_SIZE;
 
     fn diagnostic_msg(code: u32, message: &str) -> Value {
         json!({
@@ -110,6 +1243,10 @@ mod tests {
         })
     }
 
+    fn state(mode: TranslationMode) -> ProxyState {
+        ProxyState::new(ERRORS.clone(), Config::new(mode))
+    }
+
     fn extract_message(output: &[u8]) -> String {
         let v: Value = serde_json::from_slice(output).unwrap();
         v["params"]["diagnostics"][0]["message"]
@@ -121,7 +1258,7 @@ mod tests {
     #[test]
     fn test_translates_known_error() {
         let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
-        let output = transform_if_diagnostics(&input, TranslationMode::Append);
+        let output = state(TranslationMode::Append).transform_if_diagnostics(&input);
         let msg = extract_message(&output);
 
         assert!(msg.contains("Property 'foo' does not exist on type 'Bar'."));
@@ -131,7 +1268,7 @@ mod tests {
     #[test]
     fn test_unknown_error_passthrough() {
         let input = serde_json::to_vec(&diagnostic_msg(99999, "Unknown error")).unwrap();
-        let output = transform_if_diagnostics(&input, TranslationMode::Append);
+        let output = state(TranslationMode::Append).transform_if_diagnostics(&input);
 
         assert_eq!(extract_message(&output), "Unknown error");
     }
@@ -139,8 +1276,979 @@ mod tests {
     #[test]
     fn test_non_diagnostic_passthrough() {
         let input = br#"{"jsonrpc":"2.0","method":"initialize","params":{}}"#;
-        let output = transform_if_diagnostics(input, TranslationMode::Append);
+        let output = state(TranslationMode::Append).transform_if_diagnostics(input);
 
         assert_eq!(input.as_slice(), output.as_ref());
     }
+
+    #[test]
+    fn test_workspace_catalog_overrides_base() {
+        let root = std::env::temp_dir().join(format!("ts-error-translator-ws-{}", std::process::id()));
+        std::fs::create_dir_all(root.join(".ts-error-translator")).unwrap();
+        std::fs::write(
+            root.join(".ts-error-translator/catalog.toml"),
+            "[[error]]\ncode = 2339\nmessage = \"Workspace says no '{0}'.\"\n",
+        )
+        .unwrap();
+
+        let init = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"rootPath": root.to_str().unwrap()}
+        });
+        let mut state = state(TranslationMode::Replace);
+        state.handle_editor_message(&serde_json::to_vec(&init).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
+        let output = state.transform_if_diagnostics(&input);
+        assert_eq!(extract_message(&output), "● Workspace says no 'foo'.");
+    }
+
+    #[test]
+    fn test_translates_related_information() {
+        let location = json!({
+            "uri": "file:///test.ts",
+            "range": {"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 7}}
+        });
+        let input = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///test.ts",
+                "diagnostics": [{
+                    "message": "Property 'foo' does not exist on type 'Bar'.",
+                    "code": 2339,
+                    "relatedInformation": [
+                        {"location": location, "message": "Cannot find name 'baz'."},
+                        {"location": location, "message": "'baz' is declared here."},
+                        {"location": location, "message": "Something unrelated."}
+                    ]
+                }]
+            }
+        });
+        let input = serde_json::to_vec(&input).unwrap();
+        let output = state(TranslationMode::Replace).transform_if_diagnostics(&input);
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        let related = &v["params"]["diagnostics"][0]["relatedInformation"];
+
+        assert_eq!(
+            related[0]["message"],
+            "● I can't find 'baz' - it might not be imported or defined."
+        );
+        assert_eq!(related[0]["location"], location);
+        assert_eq!(related[1]["message"], "● 'baz' is defined here.");
+        assert_eq!(related[2]["message"], "Something unrelated.");
+    }
+
+    #[test]
+    fn test_code_action_gets_original_diagnostic() {
+        let mut state = state(TranslationMode::Replace);
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
+        let output = state.transform_if_diagnostics(&input);
+        let published: Value = serde_json::from_slice(&output).unwrap();
+        let translated = published["params"]["diagnostics"][0].clone();
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "textDocument/codeAction",
+            "params": {
+                "textDocument": {"uri": "file:///test.ts"},
+                "range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}},
+                "context": {"diagnostics": [translated]}
+            }
+        });
+        let request = serde_json::to_vec(&request).unwrap();
+        let Route::Forward(forwarded) = state.handle_editor_message(&request) else {
+            panic!("codeAction should be forwarded");
+        };
+        let v: Value = serde_json::from_slice(&forwarded).unwrap();
+
+        assert_eq!(
+            v["params"]["context"]["diagnostics"][0]["message"],
+            "Property 'foo' does not exist on type 'Bar'."
+        );
+    }
+
+    #[test]
+    fn test_translates_document_pull_diagnostics() {
+        let mut state = state(TranslationMode::Replace);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "textDocument/diagnostic",
+            "params": {"textDocument": {"uri": "file:///a.ts"}}
+        });
+        state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": {
+                "kind": "full",
+                "items": [{"message": "Cannot find name 'x'.", "code": 2304}],
+                "relatedDocuments": {
+                    "file:///b.ts": {"kind": "full", "items": [{"message": "Cannot find name 'y'.", "code": 2304}]},
+                    "file:///c.ts": {"kind": "unchanged", "resultId": "1"}
+                }
+            }
+        });
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            v["result"]["items"][0]["message"],
+            "● I can't find 'x' - it might not be imported or defined."
+        );
+        assert_eq!(
+            v["result"]["relatedDocuments"]["file:///b.ts"]["items"][0]["message"],
+            "● I can't find 'y' - it might not be imported or defined."
+        );
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    fn test_translates_workspace_pull_diagnostics() {
+        let mut state = state(TranslationMode::Replace);
+        let request = json!({"jsonrpc": "2.0", "id": "w1", "method": "workspace/diagnostic", "params": {"previousResultIds": []}});
+        state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": "w1",
+            "result": {"items": [{"kind": "full", "uri": "file:///a.ts", "version": 1, "items": [{"message": "Cannot find name 'x'.", "code": 2304}]}]}
+        });
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            v["result"]["items"][0]["items"][0]["message"],
+            "● I can't find 'x' - it might not be imported or defined."
+        );
+    }
+
+    #[test]
+    fn test_warns_once_about_unknown_settings() {
+        let mut state = state(TranslationMode::Replace);
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeConfiguration",
+            "params": {"settings": {"tsErrorTranslator": {"mode": "append", "disabledCode": [2322]}}}
+        });
+        let change = serde_json::to_vec(&change).unwrap();
+        state.handle_editor_message(&change);
+        let warnings = state.take_outgoing();
+        assert_eq!(warnings.len(), 1);
+        let warning: Value = serde_json::from_slice(&warnings[0]).unwrap();
+        assert_eq!(warning["method"], "window/showMessage");
+        assert!(warning["params"]["message"].as_str().unwrap().contains("disabledCode"));
+        assert_eq!(state.config.mode, TranslationMode::Append);
+
+        state.handle_editor_message(&change);
+        assert!(state.take_outgoing().is_empty());
+    }
+
+    #[test]
+    fn test_partial_results_forgotten_per_request() {
+        let mut state = state(TranslationMode::Replace);
+        for (id, token) in [(1, "a"), (2, "b"), (3, "c")] {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "workspace/diagnostic",
+                "params": {"previousResultIds": [], "partialResultToken": token}
+            });
+            state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+        }
+        let progress = |token: &str| {
+            let item = json!({"kind": "full", "uri": "file:///a.ts", "items": [{"message": "Cannot find name 'x'.", "code": 2304}]});
+            let message = json!({"jsonrpc": "2.0", "method": "$/progress", "params": {"token": token, "value": {"items": [item]}}});
+            serde_json::to_vec(&message).unwrap()
+        };
+
+        // Identical pending requests keep each other's tokens.
+        let response = json!({"jsonrpc": "2.0", "id": 1, "result": {"items": []}});
+        state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap());
+        assert!(matches!(state.transform_if_diagnostics(&progress("a")), Cow::Borrowed(_)));
+        assert!(matches!(state.transform_if_diagnostics(&progress("b")), Cow::Owned(_)));
+
+        let error = json!({"jsonrpc": "2.0", "id": 2, "error": {"code": -32603, "message": "failed"}});
+        state.transform_if_diagnostics(&serde_json::to_vec(&error).unwrap());
+        let cancel = json!({"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": 3}});
+        state.handle_editor_message(&serde_json::to_vec(&cancel).unwrap());
+        assert!(state.partial_results.is_empty());
+        assert!(state.partial_tokens.is_empty());
+        assert!(state.pending.is_empty());
+    }
+
+    #[test]
+    fn test_untracked_response_passthrough() {
+        let mut state = state(TranslationMode::Replace);
+        let response = br#"{"jsonrpc":"2.0","id":9,"result":{"items":[{"message":"Cannot find name 'x'.","code":2304}]}}"#;
+        let output = state.transform_if_diagnostics(response);
+
+        assert_eq!(response.as_slice(), output.as_ref());
+    }
+
+    fn hover_round_trip(state: &mut ProxyState, character: u32, result: Value) -> Value {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 11,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": {"uri": "file:///test.ts"},
+                "position": {"line": 0, "character": character}
+            }
+        });
+        state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+        let response = json!({"jsonrpc": "2.0", "id": 11, "result": result});
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap()).into_owned();
+        serde_json::from_slice(&output).unwrap()
+    }
+
+    fn publish_type_mismatch(state: &mut ProxyState) {
+        let publish = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///test.ts",
+                "diagnostics": [{
+                    "range": {"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 8}},
+                    "message": "Type 'string' is not assignable to type 'number'.",
+                    "code": 2322
+                }]
+            }
+        });
+        state.transform_if_diagnostics(&serde_json::to_vec(&publish).unwrap());
+    }
+
+    #[test]
+    fn test_hover_appends_explanation() {
+        let mut state = state(TranslationMode::Replace);
+        publish_type_mismatch(&mut state);
+
+        let v = hover_round_trip(
+            &mut state,
+            7,
+            json!({"contents": {"kind": "markdown", "value": "```ts\nconst id: number\n```"}}),
+        );
+        let value = v["result"]["contents"]["value"].as_str().unwrap();
+        assert!(value.starts_with("```ts\nconst id: number\n```"));
+        assert!(value.contains("**TS2322**"));
+        assert!(value.contains("`string` somewhere that expects `number`"));
+    }
+
+    #[test]
+    fn test_hover_null_result_gets_explanation() {
+        let mut state = state(TranslationMode::Replace);
+        publish_type_mismatch(&mut state);
+
+        let v = hover_round_trip(&mut state, 6, Value::Null);
+        assert_eq!(v["result"]["contents"]["kind"], "markdown");
+        assert_eq!(v["result"]["range"]["start"]["character"], 6);
+    }
+
+    #[test]
+    fn test_hover_outside_diagnostic_untouched() {
+        let mut state = state(TranslationMode::Replace);
+        publish_type_mismatch(&mut state);
+
+        let v = hover_round_trip(&mut state, 20, json!({"contents": "plain"}));
+        assert_eq!(v["result"]["contents"], "plain");
+    }
+
+    #[test]
+    fn test_code_action_response_gets_explain_action() {
+        let mut state = state(TranslationMode::Replace);
+        publish_type_mismatch(&mut state);
+        let translated = json!({
+            "range": {"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 8}},
+            "message": "● I was expecting a type matching 'number' but instead you passed 'string'.",
+            "code": 2322
+        });
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 21,
+            "method": "textDocument/codeAction",
+            "params": {
+                "textDocument": {"uri": "file:///test.ts"},
+                "range": {"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 6}},
+                "context": {"diagnostics": [translated]}
+            }
+        });
+        state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+
+        let response = json!({"jsonrpc": "2.0", "id": 21, "result": [{"title": "Server fix"}]});
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+
+        let actions = v["result"].as_array().unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[1]["title"], "Explain TS2322");
+        assert_eq!(actions[1]["diagnostics"][0], translated);
+        assert_eq!(
+            actions[1]["command"]["arguments"][0]["message"],
+            "Type 'string' is not assignable to type 'number'."
+        );
+    }
+
+    #[test]
+    fn test_execute_explain_is_answered_by_proxy() {
+        let mut state = state(TranslationMode::Replace);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 5,
+            "method": "workspace/executeCommand",
+            "params": {
+                "command": EXPLAIN_COMMAND,
+                "arguments": [{"code": 2304, "message": "Cannot find name 'x'."}]
+            }
+        });
+        let Route::Respond(replies) = state.handle_editor_message(&serde_json::to_vec(&request).unwrap()) else {
+            panic!("explain command should not reach the server");
+        };
+
+        let replies: Vec<Value> = replies.iter().map(|r| serde_json::from_slice(r).unwrap()).collect();
+        assert_eq!(replies[0]["id"], 5);
+        assert_eq!(replies[1]["method"], "window/showMessage");
+        assert!(replies[1]["params"]["message"].as_str().unwrap().contains("# TS2304"));
+    }
+
+    #[test]
+    fn test_initialize_result_advertises_command() {
+        let mut state = state(TranslationMode::Replace);
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}});
+        state.handle_editor_message(&serde_json::to_vec(&request).unwrap());
+
+        let response = json!({"jsonrpc": "2.0", "id": 1, "result": {"capabilities": {"hoverProvider": true}}});
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&response).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            v["result"]["capabilities"]["executeCommandProvider"]["commands"],
+            json!([EXPLAIN_COMMAND])
+        );
+    }
+
+    #[test]
+    fn test_initialization_options_are_stripped_and_applied() {
+        let mut state = state(TranslationMode::Replace);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "capabilities": {},
+                "initializationOptions": {
+                    "tsErrorTranslator": {"mode": "append", "disabledCodes": ["TS2304"]},
+                    "preferences": {"quoteStyle": "single"}
+                }
+            }
+        });
+        let request = serde_json::to_vec(&request).unwrap();
+        let Route::Forward(forwarded) = state.handle_editor_message(&request) else {
+            panic!("initialize should be forwarded");
+        };
+        let v: Value = serde_json::from_slice(&forwarded).unwrap();
+        assert_eq!(v["params"]["initializationOptions"], json!({"preferences": {"quoteStyle": "single"}}));
+
+        let input = serde_json::to_vec(&diagnostic_msg(2304, "Cannot find name 'foo'.")).unwrap();
+        assert_eq!(state.transform_if_diagnostics(&input).as_ref(), input.as_slice());
+
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
+        let msg = extract_message(&state.transform_if_diagnostics(&input));
+        assert!(msg.starts_with("Property 'foo' does not exist on type 'Bar'.  ● "));
+    }
+
+    #[test]
+    fn test_did_change_configuration_updates_settings() {
+        let mut state = state(TranslationMode::Replace);
+        let change = |settings: Value| {
+            serde_json::to_vec(&json!({
+                "jsonrpc": "2.0",
+                "method": "workspace/didChangeConfiguration",
+                "params": {"settings": settings}
+            }))
+            .unwrap()
+        };
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
+
+        let disable = change(json!({"tsErrorTranslator": {"enabled": false}, "typescript": {}}));
+        let Route::Forward(forwarded) = state.handle_editor_message(&disable) else {
+            panic!("didChangeConfiguration should be forwarded");
+        };
+        let v: Value = serde_json::from_slice(&forwarded).unwrap();
+        assert_eq!(v["params"]["settings"], json!({"typescript": {}}));
+        assert_eq!(state.transform_if_diagnostics(&input).as_ref(), input.as_slice());
+
+        state.handle_editor_message(&change(json!({"tsErrorTranslator": {"bullet": "→"}})));
+        assert_eq!(
+            extract_message(&state.transform_if_diagnostics(&input)),
+            "→ You're trying to access 'foo' on an object that doesn't contain it."
+        );
+    }
+
+    #[test]
+    fn test_configuration_is_pulled_when_supported() {
+        let mut state = state(TranslationMode::Replace);
+        let init = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"capabilities": {"workspace": {"configuration": true}}}
+        });
+        state.handle_editor_message(&serde_json::to_vec(&init).unwrap());
+        assert!(state.take_outgoing().is_empty());
+
+        state.handle_editor_message(br#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#);
+        let outgoing = state.take_outgoing();
+        let request: Value = serde_json::from_slice(&outgoing[0]).unwrap();
+        assert_eq!(request["method"], "workspace/configuration");
+        assert_eq!(request["params"]["items"][0]["section"], SETTINGS_KEY);
+
+        let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": [{"enabled": false}]});
+        let Route::Respond(replies) = state.handle_editor_message(&serde_json::to_vec(&response).unwrap()) else {
+            panic!("configuration response should not reach the server");
+        };
+        assert!(replies.is_empty());
+        assert!(!state.config.enabled);
+    }
+
+    #[test]
+    fn test_project_config_file_applies() {
+        let root = std::env::temp_dir().join(format!("ts-error-translator-project-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(".ts-error-translator.toml"), "mode = \"append\"\nenabledCodes = [2339]\n").unwrap();
+
+        let init = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"rootPath": root.to_str().unwrap(), "capabilities": {}}
+        });
+        let mut state = state(TranslationMode::Replace);
+        state.handle_editor_message(&serde_json::to_vec(&init).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "Property 'foo' does not exist on type 'Bar'.")).unwrap();
+        let msg = extract_message(&state.transform_if_diagnostics(&input));
+        assert!(msg.starts_with("Property 'foo' does not exist on type 'Bar'.  ● "));
+
+        let input = serde_json::to_vec(&diagnostic_msg(2304, "Cannot find name 'foo'.")).unwrap();
+        assert_eq!(state.transform_if_diagnostics(&input).as_ref(), input.as_slice());
+    }
+
+    #[test]
+    fn test_policy_rules_apply_per_diagnostic() {
+        let mut state = state(TranslationMode::Replace);
+        let settings = json!({
+            "tsErrorTranslator": {
+                "rules": [
+                    {"codes": [2322], "translate": false},
+                    {"severities": ["warning"], "mode": "append"}
+                ]
+            }
+        });
+        let change = json!({"jsonrpc": "2.0", "method": "workspace/didChangeConfiguration", "params": {"settings": settings}});
+        state.handle_editor_message(&serde_json::to_vec(&change).unwrap());
+
+        let input = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///test.ts",
+                "diagnostics": [
+                    {"message": "Type 'string' is not assignable to type 'number'.", "code": 2322, "severity": 1},
+                    {"message": "Cannot find name 'foo'.", "code": 2304, "severity": 2},
+                    {"message": "Cannot find name 'bar'.", "code": 2304, "severity": 1}
+                ]
+            }
+        });
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&input).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        let diagnostics = &v["params"]["diagnostics"];
+
+        assert_eq!(diagnostics[0]["message"], "Type 'string' is not assignable to type 'number'.");
+        assert!(diagnostics[1]["message"].as_str().unwrap().starts_with("Cannot find name 'foo'.  ● "));
+        assert_eq!(
+            diagnostics[2]["message"],
+            "● I can't find 'bar' - it might not be imported or defined."
+        );
+    }
+
+    #[test]
+    fn test_related_mode_keeps_message() {
+        let input = serde_json::to_vec(&diagnostic_msg(2304, "Cannot find name 'foo'.")).unwrap();
+        let output = state(TranslationMode::Related).transform_if_diagnostics(&input).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        let diagnostic = &v["params"]["diagnostics"][0];
+
+        assert_eq!(diagnostic["message"], "Cannot find name 'foo'.");
+        assert_eq!(
+            diagnostic["relatedInformation"][0]["message"],
+            "● I can't find 'foo' - it might not be imported or defined."
+        );
+        assert_eq!(diagnostic["relatedInformation"][0]["location"]["uri"], "file:///test.ts");
+    }
+
+    #[test]
+    fn test_diagnostic_mode_adds_information_diagnostic() {
+        let mut state = state(TranslationMode::Diagnostic);
+        let range = json!({"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 3}});
+        let input = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///test.ts",
+                "diagnostics": [{"range": range, "message": "Cannot find name 'foo'.", "code": 2304, "severity": 1}]
+            }
+        });
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&input).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        let diagnostics = v["params"]["diagnostics"].as_array().unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0], input["params"]["diagnostics"][0]);
+        assert_eq!(diagnostics[1]["severity"], 3);
+        assert_eq!(diagnostics[1]["range"], range);
+        assert_eq!(diagnostics[1]["source"], TRANSLATION_SOURCE);
+
+        // The added diagnostic never reaches the server in a code action request.
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "method": "textDocument/codeAction",
+            "params": {
+                "textDocument": {"uri": "file:///test.ts"},
+                "range": range,
+                "context": {"diagnostics": diagnostics}
+            }
+        });
+        let request = serde_json::to_vec(&request).unwrap();
+        let Route::Forward(forwarded) = state.handle_editor_message(&request) else {
+            panic!("codeAction should be forwarded");
+        };
+        let v: Value = serde_json::from_slice(&forwarded).unwrap();
+        assert_eq!(v["params"]["context"]["diagnostics"], json!([input["params"]["diagnostics"][0]]));
+    }
+
+    #[test]
+    fn test_code_description_links_known_codes() {
+        let mut state = state(TranslationMode::Replace);
+        let init = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "capabilities": {"textDocument": {"publishDiagnostics": {"codeDescriptionSupport": true}}},
+                "initializationOptions": {"tsErrorTranslator": {"docsUrl": "https://wiki/{code}"}}
+            }
+        });
+        state.handle_editor_message(&serde_json::to_vec(&init).unwrap());
+
+        let input = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": "file:///test.ts",
+                "diagnostics": [
+                    {"message": "Cannot find name 'foo'.", "code": 2304},
+                    {"message": "Cannot find name 'bar'.", "code": 2304, "codeDescription": {"href": "https://server/2304"}},
+                    {"message": "Unknown error", "code": 99999}
+                ]
+            }
+        });
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&input).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        let diagnostics = &v["params"]["diagnostics"];
+
+        assert_eq!(diagnostics[0]["codeDescription"]["href"], "https://wiki/2304");
+        assert_eq!(diagnostics[1]["codeDescription"]["href"], "https://server/2304");
+        assert!(diagnostics[2].get("codeDescription").is_none());
+
+        // Known codes a rule leaves untranslated are linked too.
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeConfiguration",
+            "params": {"settings": {"tsErrorTranslator": {"rules": [{"codes": ["TS2304"], "translate": false}]}}}
+        });
+        state.handle_editor_message(&serde_json::to_vec(&change).unwrap());
+        let output = state.transform_if_diagnostics(&serde_json::to_vec(&input).unwrap()).into_owned();
+        let v: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(v["params"]["diagnostics"][0]["message"], "Cannot find name 'foo'.");
+        assert_eq!(v["params"]["diagnostics"][0]["codeDescription"]["href"], "https://wiki/2304");
+    }
+
+    #[test]
+    fn test_locale_from_initialize_and_settings() {
+        let mut state = state(TranslationMode::Replace);
+        let init = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"capabilities": {}, "locale": "ja"}
+        });
+        state.handle_editor_message(&serde_json::to_vec(&init).unwrap());
+
+        let input = serde_json::to_vec(&diagnostic_msg(2339, "プロパティ 'foo' は型 'Bar' に存在しません。")).unwrap();
+        assert_eq!(
+            extract_message(&state.transform_if_diagnostics(&input)),
+            "● You're trying to access 'foo' on an object that doesn't contain it."
+        );
+
+        // A configured locale wins over the editor's.
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeConfiguration",
+            "params": {"settings": {"tsErrorTranslator": {"locale": "de"}}}
+        });
+        state.handle_editor_message(&serde_json::to_vec(&change).unwrap());
+        let input = serde_json::to_vec(&diagnostic_msg(
+            2339,
+            "Die Eigenschaft \"foo\" ist für den Typ \"Bar\" nicht vorhanden.",
+        ))
+        .unwrap();
+        assert_eq!(
+            extract_message(&state.transform_if_diagnostics(&input)),
+            "● You're trying to access 'foo' on an object that doesn't contain it."
+        );
+    }
+
+    /// Hands out in-memory servers, one per connection.
+    struct FakeServers(Vec<tokio::io::DuplexStream>);
+
+    impl Connect for FakeServers {
+        type Reader = tokio::io::ReadHalf<tokio::io::DuplexStream>;
+        type Writer = tokio::io::WriteHalf<tokio::io::DuplexStream>;
+
+        async fn connect(&mut self) -> std::io::Result<(Self::Reader, Self::Writer)> {
+            let stream = self.0.pop().ok_or_else(|| std::io::Error::other("no more servers"))?;
+            Ok(tokio::io::split(stream))
+        }
+    }
+
+    async fn send(writer: &mut (impl AsyncWrite + Unpin), message: Value) {
+        write_message(writer, &serde_json::to_vec(&message).unwrap()).await.unwrap();
+    }
+
+    async fn receive(reader: &mut BufReader<impl AsyncRead + Unpin>) -> Value {
+        serde_json::from_slice(&read_message(reader, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restarts_server_and_replays_session() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let (second, second_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![second, first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "initialize");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": {"capabilities": {}}})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 1);
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "initialized", "params": {}})).await;
+            let document = json!({"uri": "file:///a.ts", "languageId": "typescript", "version": 1, "text": "let a"});
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": document}})).await;
+            let change = json!({"textDocument": {"uri": "file:///a.ts", "version": 2}, "contentChanges": [{"text": "let b"}]});
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": change})).await;
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {}})).await;
+            for _ in 0..4 {
+                receive(&mut server_reader).await;
+            }
+
+            // The server dies with the hover unanswered.
+            drop((server, server_reader));
+            let failed = receive(&mut editor_reader).await;
+            assert_eq!(failed["id"], 2);
+            assert!(failed.get("error").is_some());
+            assert_eq!(receive(&mut editor_reader).await["method"], "window/showMessage");
+
+            let (server_reader, mut server) = tokio::io::split(second_server);
+            let mut server_reader = BufReader::new(server_reader);
+            let initialize = receive(&mut server_reader).await;
+            assert_eq!(initialize["method"], "initialize");
+            assert_eq!(initialize["params"], json!({"capabilities": {}}));
+            send(&mut server, json!({"jsonrpc": "2.0", "id": initialize["id"], "result": {"capabilities": {}}})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "initialized");
+            let reopened = receive(&mut server_reader).await;
+            assert_eq!(reopened["method"], "textDocument/didOpen");
+            assert_eq!(reopened["params"]["textDocument"]["text"], "let b");
+            assert_eq!(reopened["params"]["textDocument"]["version"], 2);
+            (server, server_reader)
+        };
+
+        let (result, _server) = tokio::join!(proxy, driver);
+        // The driver dropping the editor's end finishes the session.
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_exit_ends_session_without_restart() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 1);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            // The editor keeps its end open; the proxy is done regardless.
+            (editor, editor_reader, server, server_reader)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+        assert!(servers.0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_skipped_messages_are_answered() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let mut config = Config::new(TranslationMode::Replace);
+        config.max_message_size = 200;
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), config, &trace);
+        let padding = "x".repeat(200);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            // An oversized request is answered with an error rather than dropped.
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {"padding": padding}})).await;
+            let reply = receive(&mut editor_reader).await;
+            assert_eq!(reply["id"], 3);
+            assert_eq!(reply["error"]["code"], -32600);
+
+            // So is a request whose oversized response the proxy skips.
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 4, "method": "textDocument/hover", "params": {}})).await;
+            assert_eq!(receive(&mut server_reader).await["id"], 4);
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 4, "result": {"contents": padding}})).await;
+            let reply = receive(&mut editor_reader).await;
+            assert_eq!(reply["id"], 4);
+            assert_eq!(reply["error"]["code"], -32603);
+
+            // And an oversized request from the server.
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 5, "method": "workspace/configuration", "params": {"padding": padding}})).await;
+            let reply = receive(&mut server_reader).await;
+            assert_eq!(reply["id"], 5);
+            assert_eq!(reply["error"]["code"], -32600);
+
+            // Skipped notifications are logged to the editor.
+            send(&mut server, json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": {"padding": padding}})).await;
+            let log = receive(&mut editor_reader).await;
+            assert_eq!(log["method"], "window/logMessage");
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 6, "method": "shutdown"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 6, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 6);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            (editor, editor_reader, server, server_reader)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_short_content_length_gets_back_in_step() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover", "params": {}})).await;
+            assert_eq!(receive(&mut server_reader).await["id"], 1);
+            // The body runs on past its Content-Length, into the next message's header.
+            let body = br#"{"jsonrpc":"2.0","method":"window/logMessage","params":{"type":3,"message":"lost"}}"#;
+            let wire = [format!("Content-Length: {}\r\n\r\n", body.len() - 10).as_bytes(), body].concat();
+            tokio::io::AsyncWriteExt::write_all(&mut server, &wire).await.unwrap();
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": {"contents": "hover"}})).await;
+            let diagnostics = json!({"uri": "file:///a.ts", "diagnostics": [{"message": "Cannot find name 'foo'.", "code": 2304}]});
+            send(&mut server, json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": diagnostics})).await;
+
+            let report = receive(&mut editor_reader).await;
+            assert_eq!(report["method"], "window/logMessage");
+            assert!(report["params"]["message"].as_str().unwrap().contains("not JSON"));
+            assert_eq!(receive(&mut editor_reader).await["result"]["contents"], "hover");
+            let published = receive(&mut editor_reader).await;
+            assert_eq!(published["method"], "textDocument/publishDiagnostics");
+            assert_eq!(published["params"]["diagnostics"][0]["message"], "● I can't find 'foo' - it might not be imported or defined.");
+
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 2, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 2);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            (editor, editor_reader, server, server_reader)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_output_out_of_step_restarts_server() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let (second, second_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![second, first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (_first_reader, mut first) = tokio::io::split(first_server);
+
+            let garbage = "not a header\r\n".repeat(MAX_FRAMING_ERRORS as usize);
+            tokio::io::AsyncWriteExt::write_all(&mut first, garbage.as_bytes()).await.unwrap();
+            assert_eq!(receive(&mut editor_reader).await["method"], "window/logMessage");
+            assert_eq!(receive(&mut editor_reader).await["method"], "window/showMessage");
+
+            // Requests now go to the second server.
+            let (server_reader, mut server) = tokio::io::split(second_server);
+            let mut server_reader = BufReader::new(server_reader);
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 1);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            (editor, editor_reader, server, server_reader, first)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+        assert!(servers.0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_badly_framed_editor_data_is_skipped() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            tokio::io::AsyncWriteExt::write_all(&mut editor, b"Content-Length: 3\r\n\r\n{\"a").await.unwrap();
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).await;
+            let report = receive(&mut editor_reader).await;
+            assert_eq!(report["method"], "window/logMessage");
+            assert!(report["params"]["message"].as_str().unwrap().contains("from the editor"));
+
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 1);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            (editor, editor_reader, server, server_reader)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_editor_data_out_of_step_ends_session() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, _first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let garbage = "not a header\r\n".repeat(MAX_FRAMING_ERRORS as usize);
+            tokio::io::AsyncWriteExt::write_all(&mut editor, garbage.as_bytes()).await.unwrap();
+            (editor_reader, editor)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_badly_framed_server_data_is_skipped_without_restart() {
+        let (editor, proxy_editor) = tokio::io::duplex(1 << 16);
+        let (first, first_server) = tokio::io::duplex(1 << 16);
+        let mut servers = FakeServers(vec![first]);
+        let (proxy_reader, proxy_writer) = tokio::io::split(proxy_editor);
+        let trace = Trace::default();
+        let proxy = run_proxy(proxy_reader, proxy_writer, &mut servers, ERRORS.clone(), Config::new(TranslationMode::Replace), &trace);
+
+        let driver = async {
+            let (editor_reader, mut editor) = tokio::io::split(editor);
+            let mut editor_reader = BufReader::new(editor_reader);
+            let (server_reader, mut server) = tokio::io::split(first_server);
+            let mut server_reader = BufReader::new(server_reader);
+
+            tokio::io::AsyncWriteExt::write_all(&mut server, b"Starting server...\r\nContent-Length: many\r\n\r\n").await.unwrap();
+            send(&mut server, json!({"jsonrpc": "2.0", "method": "window/logMessage", "params": {"type": 3, "message": "ready"}})).await;
+            let report = receive(&mut editor_reader).await;
+            assert_eq!(report["method"], "window/logMessage");
+            assert!(report["params"]["message"].as_str().unwrap().contains("badly framed"));
+            assert_eq!(receive(&mut editor_reader).await["params"]["message"], "ready");
+
+            // Still the first server: a restart would have replayed nothing and failed to connect.
+            send(&mut editor, json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "shutdown");
+            send(&mut server, json!({"jsonrpc": "2.0", "id": 1, "result": null})).await;
+            assert_eq!(receive(&mut editor_reader).await["id"], 1);
+            send(&mut editor, json!({"jsonrpc": "2.0", "method": "exit"})).await;
+            assert_eq!(receive(&mut server_reader).await["method"], "exit");
+            (editor, editor_reader, server, server_reader)
+        };
+
+        let (result, _ends) = tokio::join!(proxy, driver);
+        result.unwrap();
+    }
 }
@@ -0,0 +1,131 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A diagnostic as the server sent it, paired with the message we showed the editor.
+pub struct StoredDiagnostic {
+    pub original: Value,
+    pub translated_message: String,
+}
+
+/// Remembers the original form of every diagnostic the proxy rewrote, per document URI.
+#[derive(Default)]
+pub struct DiagnosticStore {
+    by_uri: HashMap<String, Vec<StoredDiagnostic>>,
+}
+
+impl DiagnosticStore {
+    /// Replaces everything known about `uri`, as each publish carries the full set.
+    pub fn replace(&mut self, uri: &str, diagnostics: Vec<StoredDiagnostic>) {
+        if diagnostics.is_empty() {
+            self.by_uri.remove(uri);
+        } else {
+            self.by_uri.insert(uri.to_string(), diagnostics);
+        }
+    }
+
+    /// Rewritten diagnostics of `uri` whose range covers `position`.
+    pub fn at_position<'a>(
+        &'a self,
+        uri: &str,
+        position: &'a Value,
+    ) -> impl Iterator<Item = &'a StoredDiagnostic> {
+        self.by_uri
+            .get(uri)
+            .into_iter()
+            .flatten()
+            .filter(move |s| s.original.get("range").is_some_and(|r| range_contains(r, position)))
+    }
+
+    /// Finds the original of a diagnostic echoed back by the editor, matching
+    /// on range and code and preferring an exact match on the translated text.
+    pub fn original_for(&self, uri: &str, diagnostic: &Value) -> Option<&Value> {
+        let stored = self.by_uri.get(uri)?;
+        let mut candidates = stored.iter().filter(|s| {
+            s.original.get("range") == diagnostic.get("range")
+                && s.original.get("code") == diagnostic.get("code")
+        });
+
+        let message = diagnostic.get("message").and_then(Value::as_str);
+        let first = candidates.clone().next();
+        candidates
+            .find(|s| Some(s.translated_message.as_str()) == message)
+            .or(first)
+            .map(|s| &s.original)
+    }
+}
+
+/// Whether an LSP `position` lies within `range` (both ends inclusive).
+fn range_contains(range: &Value, position: &Value) -> bool {
+    let point = |v: &Value| {
+        Some((v.get("line")?.as_u64()?, v.get("character")?.as_u64()?))
+    };
+    let (Some(start), Some(end), Some(pos)) = (
+        range.get("start").and_then(point),
+        range.get("end").and_then(point),
+        point(position),
+    ) else {
+        return false;
+    };
+    start <= pos && pos <= end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn range(line: u32) -> Value {
+        json!({"start": {"line": line, "character": 0}, "end": {"line": line, "character": 5}})
+    }
+
+    fn stored(line: u32, original: &str, translated: &str) -> StoredDiagnostic {
+        StoredDiagnostic {
+            original: json!({"range": range(line), "code": 2322, "message": original}),
+            translated_message: translated.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_matches_on_range_and_code() {
+        let mut store = DiagnosticStore::default();
+        store.replace("file:///a.ts", vec![stored(1, "one", "● 1"), stored(2, "two", "● 2")]);
+
+        let echoed = json!({"range": range(2), "code": 2322, "message": "● 2"});
+        let original = store.original_for("file:///a.ts", &echoed).unwrap();
+        assert_eq!(original["message"], "two");
+    }
+
+    #[test]
+    fn test_prefers_matching_translation() {
+        let mut store = DiagnosticStore::default();
+        store.replace("file:///a.ts", vec![stored(1, "one", "● 1"), stored(1, "uno", "● uno")]);
+
+        let echoed = json!({"range": range(1), "code": 2322, "message": "● uno"});
+        let original = store.original_for("file:///a.ts", &echoed).unwrap();
+        assert_eq!(original["message"], "uno");
+    }
+
+    #[test]
+    fn test_replace_clears_uri() {
+        let mut store = DiagnosticStore::default();
+        store.replace("file:///a.ts", vec![stored(1, "one", "● 1")]);
+        store.replace("file:///a.ts", Vec::new());
+
+        let echoed = json!({"range": range(1), "code": 2322, "message": "● 1"});
+        assert!(store.original_for("file:///a.ts", &echoed).is_none());
+    }
+
+    #[test]
+    fn test_at_position() {
+        let mut store = DiagnosticStore::default();
+        store.replace("file:///a.ts", vec![stored(1, "one", "● 1"), stored(2, "two", "● 2")]);
+
+        let position = json!({"line": 2, "character": 3});
+        let found: Vec<_> = store.at_position("file:///a.ts", &position).collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].original["message"], "two");
+
+        let outside = json!({"line": 2, "character": 9});
+        assert_eq!(store.at_position("file:///a.ts", &outside).count(), 0);
+    }
+}
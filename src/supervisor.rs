@@ -0,0 +1,569 @@
+use crate::transport::{self, Address};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+/// Consecutive restarts attempted before giving up on the server.
+const MAX_RESTARTS: u32 = 5;
+
+/// Delay before the first restart, doubled for each consecutive one.
+const FIRST_RESTART_DELAY: Duration = Duration::from_millis(250);
+
+/// A server that stayed up this long is considered healthy again.
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// LSP `RequestFailed` error code.
+const REQUEST_FAILED: i64 = -32803;
+
+/// A way to (re)connect to the language server.
+pub trait Connect {
+    type Reader: AsyncRead + Unpin;
+    type Writer: AsyncWrite + Unpin;
+
+    /// Opens a new connection, replacing the previous one.
+    fn connect(&mut self) -> impl Future<Output = std::io::Result<(Self::Reader, Self::Writer)>>;
+}
+
+/// Spawns the language server, and again whenever it has to be restarted.
+pub struct ServerProcess {
+    program: String,
+    args: Vec<String>,
+    child: Option<Child>,
+}
+
+impl ServerProcess {
+    pub fn new(program: String, args: Vec<String>) -> Self {
+        Self {
+            program,
+            args,
+            child: None,
+        }
+    }
+
+    /// Passes a termination signal on to the current server.
+    #[cfg(unix)]
+    pub fn signal(&mut self, signal: i32) {
+        if let Some(pid) = self.child.as_ref().and_then(Child::id) {
+            // SAFETY: `pid` is our own child, which has not been reaped yet.
+            unsafe {
+                libc::kill(pid as libc::pid_t, signal);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn signal(&mut self, _signal: i32) {
+        if let Some(child) = &mut self.child {
+            let _ = child.start_kill();
+        }
+    }
+
+    /// Waits up to `grace` for the server to exit, then kills it.
+    /// `None` if no server was ever started.
+    pub async fn finish(&mut self, grace: Duration) -> std::io::Result<Option<ExitStatus>> {
+        let Some(child) = &mut self.child else {
+            return Ok(None);
+        };
+        match tokio::time::timeout(grace, child.wait()).await {
+            Ok(status) => status.map(Some),
+            Err(_) => {
+                eprintln!(
+                    "ts-error-translator-proxy: language server did not exit within {:?}; killing it",
+                    grace
+                );
+                child.kill().await?;
+                child.wait().await.map(Some)
+            }
+        }
+    }
+}
+
+/// The server a session talks to: one the proxy starts itself, or one that
+/// is already running and listening at an address.
+pub enum LanguageServer {
+    Process(ServerProcess),
+    Remote(Address),
+}
+
+impl LanguageServer {
+    /// Passes a termination signal on to a server the proxy started.
+    pub fn signal(&mut self, signal: i32) {
+        if let LanguageServer::Process(process) = self {
+            process.signal(signal);
+        }
+    }
+
+    /// Stops a server the proxy started, see [`ServerProcess::finish`].
+    /// A remote server keeps running.
+    pub async fn finish(&mut self, grace: Duration) -> std::io::Result<Option<ExitStatus>> {
+        match self {
+            LanguageServer::Process(process) => process.finish(grace).await,
+            LanguageServer::Remote(_) => Ok(None),
+        }
+    }
+}
+
+impl Connect for LanguageServer {
+    type Reader = transport::Reader;
+    type Writer = transport::Writer;
+
+    async fn connect(&mut self) -> std::io::Result<(transport::Reader, transport::Writer)> {
+        match self {
+            LanguageServer::Process(process) => {
+                let (reader, writer) = process.connect().await?;
+                Ok((Box::new(reader), Box::new(writer)))
+            }
+            LanguageServer::Remote(address) => transport::connect(address).await,
+        }
+    }
+}
+
+/// Resolves to the number of the first SIGTERM or SIGINT the proxy receives.
+#[cfg(unix)]
+pub async fn termination_signal() -> i32 {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut terminate), Ok(mut interrupt)) = (signal(SignalKind::terminate()), signal(SignalKind::interrupt()))
+    else {
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = terminate.recv() => libc::SIGTERM,
+        _ = interrupt.recv() => libc::SIGINT,
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn termination_signal() -> i32 {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+    2
+}
+
+/// The exit code a shell would report for `status`: the code itself, or 128 plus the signal.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+impl Connect for ServerProcess {
+    type Reader = ChildStdout;
+    type Writer = ChildStdin;
+
+    async fn connect(&mut self) -> std::io::Result<(ChildStdout, ChildStdin)> {
+        if let Some(mut previous) = self.child.take() {
+            // Normally gone already; a server that merely stopped talking is put down.
+            let _ = previous.start_kill();
+            if let Ok(status) = previous.wait().await {
+                eprintln!("ts-error-translator-proxy: language server exited ({})", status);
+            }
+        }
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().expect("Failed to open LSP stdin");
+        let stdout = child.stdout.take().expect("Failed to open LSP stdout");
+        self.child = Some(child);
+        Ok((stdout, stdin))
+    }
+}
+
+/// Spaces out restarts of a server that keeps exiting, and gives up eventually.
+#[derive(Default)]
+pub struct Backoff {
+    attempts: u32,
+}
+
+impl Backoff {
+    /// Delay before restarting a server that ran for `uptime`, or `None` to give up.
+    pub fn next_delay(&mut self, uptime: Duration) -> Option<Duration> {
+        if uptime >= STABLE_UPTIME {
+            self.attempts = 0;
+        }
+        if self.attempts >= MAX_RESTARTS {
+            return None;
+        }
+        let delay = FIRST_RESTART_DELAY * 2u32.pow(self.attempts);
+        self.attempts += 1;
+        Some(delay)
+    }
+}
+
+/// Unit of LSP `Position.character`, as negotiated in `initialize`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn parse(name: &str) -> Self {
+        match name {
+            "utf-8" => PositionEncoding::Utf8,
+            "utf-32" => PositionEncoding::Utf32,
+            _ => PositionEncoding::Utf16,
+        }
+    }
+
+    fn width(self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// How far the editor got in the LSP shutdown sequence.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Lifecycle {
+    #[default]
+    Running,
+    /// `shutdown` was sent; the server may exit any time now
+    ShuttingDown,
+    /// `exit` was sent; nothing more goes to the server
+    Exited,
+}
+
+struct Document {
+    language_id: Value,
+    version: Value,
+    text: String,
+}
+
+/// What a restarted server needs to pick up where the previous one left off:
+/// the handshake, the last configuration and the open documents' current text.
+#[derive(Default)]
+pub struct Session {
+    /// `initialize` params as forwarded to the server
+    initialize: Option<Value>,
+    initialized: Option<Value>,
+    /// Params of the last `workspace/didChangeConfiguration`
+    configuration: Option<Value>,
+    documents: BTreeMap<String, Document>,
+    /// Forwarded editor requests the server has yet to answer: serialized id -> (id, method)
+    in_flight: HashMap<String, (Value, String)>,
+    encoding: PositionEncoding,
+    lifecycle: Lifecycle,
+}
+
+impl Session {
+    /// Records a message the editor sent on to the server.
+    pub fn record_editor_message(&mut self, message: &Value) {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return;
+        };
+        if let Some(id) = message.get("id") {
+            self.in_flight.insert(id.to_string(), (id.clone(), method.to_string()));
+        }
+        let params = message.get("params");
+        match method {
+            "initialize" => self.initialize = params.cloned(),
+            "initialized" => self.initialized = Some(params.cloned().unwrap_or_else(|| json!({}))),
+            "shutdown" if self.lifecycle == Lifecycle::Running => self.lifecycle = Lifecycle::ShuttingDown,
+            "exit" => self.lifecycle = Lifecycle::Exited,
+            "workspace/didChangeConfiguration" => self.configuration = params.cloned(),
+            "textDocument/didOpen" => {
+                let Some(document) = params.and_then(|p| p.get("textDocument")) else {
+                    return;
+                };
+                let (Some(uri), Some(text)) = (
+                    document.get("uri").and_then(Value::as_str),
+                    document.get("text").and_then(Value::as_str),
+                ) else {
+                    return;
+                };
+                self.documents.insert(
+                    uri.to_string(),
+                    Document {
+                        language_id: document.get("languageId").cloned().unwrap_or(Value::Null),
+                        version: document.get("version").cloned().unwrap_or(Value::Null),
+                        text: text.to_string(),
+                    },
+                );
+            }
+            "textDocument/didChange" => {
+                let uri = params.and_then(|p| p.pointer("/textDocument/uri")).and_then(Value::as_str);
+                let Some(document) = uri.and_then(|uri| self.documents.get_mut(uri)) else {
+                    return;
+                };
+                if let Some(version) = params.and_then(|p| p.pointer("/textDocument/version")) {
+                    document.version = version.clone();
+                }
+                let changes = params.and_then(|p| p.get("contentChanges")).and_then(Value::as_array);
+                for change in changes.into_iter().flatten() {
+                    let Some(text) = change.get("text").and_then(Value::as_str) else {
+                        continue;
+                    };
+                    match change.get("range") {
+                        Some(range) => apply_edit(&mut document.text, range, text, self.encoding),
+                        None => document.text = text.to_string(),
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params.and_then(|p| p.pointer("/textDocument/uri")).and_then(Value::as_str) {
+                    self.documents.remove(uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records a message from the server, completing the request it answers.
+    pub fn record_server_message(&mut self, message: &Value) {
+        if message.get("method").is_some() {
+            return;
+        }
+        let Some(id) = message.get("id") else {
+            return;
+        };
+        if let Some((_, method)) = self.in_flight.remove(&id.to_string()) {
+            if method == "initialize" {
+                let encoding = message.pointer("/result/capabilities/positionEncoding").and_then(Value::as_str);
+                self.encoding = encoding.map(PositionEncoding::parse).unwrap_or_default();
+            }
+        }
+    }
+
+    /// Where the session is in the shutdown sequence; a server exiting
+    /// anywhere but [`Lifecycle::Running`] was asked to.
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle
+    }
+
+    /// Error responses to the requests a server that exited will never answer.
+    pub fn fail_in_flight(&mut self) -> Vec<Value> {
+        self.in_flight
+            .drain()
+            .map(|(_, (id, method))| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": REQUEST_FAILED,
+                        "message": format!("The language server exited before answering {}", method)
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// The editor's `initialize` request again, under `id`; `None` if it never sent one.
+    pub fn initialize_request(&self, id: &str) -> Option<Value> {
+        let params = self.initialize.as_ref()?;
+        Some(json!({"jsonrpc": "2.0", "id": id, "method": "initialize", "params": params}))
+    }
+
+    /// `initialized`, the last configuration and a `didOpen` for each open
+    /// document, for after the new server answered `initialize`.
+    pub fn reopen(&self) -> Vec<Value> {
+        let mut messages = Vec::new();
+        if let Some(params) = &self.initialized {
+            messages.push(json!({"jsonrpc": "2.0", "method": "initialized", "params": params}));
+        }
+        if let Some(params) = &self.configuration {
+            messages.push(json!({"jsonrpc": "2.0", "method": "workspace/didChangeConfiguration", "params": params}));
+        }
+        for (uri, document) in &self.documents {
+            messages.push(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": document.language_id,
+                        "version": document.version,
+                        "text": document.text
+                    }
+                }
+            }));
+        }
+        messages
+    }
+}
+
+/// Replaces the text within an LSP `range` by `new_text`.
+fn apply_edit(text: &mut String, range: &Value, new_text: &str, encoding: PositionEncoding) {
+    let position = |key: &str| {
+        let line = range.pointer(&format!("/{}/line", key)).and_then(Value::as_u64)? as usize;
+        let character = range.pointer(&format!("/{}/character", key)).and_then(Value::as_u64)? as usize;
+        Some(offset(text, line, character, encoding))
+    };
+    let (Some(start), Some(end)) = (position("start"), position("end")) else {
+        return;
+    };
+    text.replace_range(start..end.max(start), new_text);
+}
+
+/// Byte offset of a position, clamped to the end of its line (or of the text).
+fn offset(text: &str, line: usize, character: usize, encoding: PositionEncoding) -> usize {
+    let Some(line_start) = (0..line).try_fold(0, |start, _| text[start..].find('\n').map(|i| start + i + 1)) else {
+        return text.len();
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' || (c == '\r' && text[line_start + i..].starts_with("\r\n")) {
+            return line_start + i;
+        }
+        units += encoding.width(c);
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn did_change(version: u64, changes: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {"textDocument": {"uri": "file:///a.ts", "version": version}, "contentChanges": changes}
+        })
+    }
+
+    fn session_with_document(text: &str) -> Session {
+        let mut session = Session::default();
+        session.record_editor_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///a.ts", "languageId": "typescript", "version": 1, "text": text}}
+        }));
+        session
+    }
+
+    fn reopened_text(session: &Session) -> String {
+        let messages = session.reopen();
+        messages.last().unwrap()["params"]["textDocument"]["text"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_applies_incremental_changes() {
+        let mut session = session_with_document("const a = 1;\nconst 😀 = b;\r\nlet c;");
+        let range = |sl, sc, el, ec| json!({"start": {"line": sl, "character": sc}, "end": {"line": el, "character": ec}});
+        session.record_editor_message(&did_change(
+            2,
+            json!([
+                {"range": range(0, 10, 0, 11), "text": "42"},
+                // The emoji is two UTF-16 code units.
+                {"range": range(1, 11, 1, 12), "text": "c"},
+                {"range": range(2, 5, 2, 99), "text": " = 3;"}
+            ]),
+        ));
+        assert_eq!(reopened_text(&session), "const a = 42;\nconst 😀 = c;\r\nlet c = 3;");
+
+        session.record_editor_message(&did_change(3, json!([{"text": "replaced"}])));
+        assert_eq!(reopened_text(&session), "replaced");
+        assert_eq!(session.reopen()[0]["params"]["textDocument"]["version"], 3);
+    }
+
+    #[test]
+    fn test_utf8_position_encoding() {
+        let mut session = session_with_document("é = 1");
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}));
+        session.record_server_message(&json!({"jsonrpc": "2.0", "id": 1, "result": {"capabilities": {"positionEncoding": "utf-8"}}}));
+        let range = json!({"start": {"line": 0, "character": 2}, "end": {"line": 0, "character": 5}});
+        session.record_editor_message(&did_change(2, json!([{"range": range, "text": " := "}])));
+        assert_eq!(reopened_text(&session), "é := 1");
+    }
+
+    #[test]
+    fn test_reopen_replays_handshake_and_open_documents() {
+        let mut session = session_with_document("x");
+        let initialize = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"rootUri": "file:///"}});
+        session.record_editor_message(&initialize);
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}));
+        session.record_editor_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///b.ts", "languageId": "typescript", "version": 1, "text": "y"}}
+        }));
+        session.record_editor_message(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": {"textDocument": {"uri": "file:///b.ts"}}
+        }));
+
+        let request = session.initialize_request("restart-1").unwrap();
+        assert_eq!(request["id"], "restart-1");
+        assert_eq!(request["params"], json!({"rootUri": "file:///"}));
+
+        let methods: Vec<_> = session.reopen().iter().map(|m| m["method"].as_str().unwrap().to_string()).collect();
+        assert_eq!(methods, vec!["initialized", "textDocument/didOpen"]);
+    }
+
+    #[test]
+    fn test_fails_unanswered_requests() {
+        let mut session = Session::default();
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover", "params": {}}));
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "id": "b", "method": "textDocument/definition", "params": {}}));
+        session.record_server_message(&json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+
+        let failed = session.fail_in_flight();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0]["id"], "b");
+        assert_eq!(failed[0]["error"]["code"], REQUEST_FAILED);
+        assert!(session.fail_in_flight().is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut session = Session::default();
+        assert_eq!(session.lifecycle(), Lifecycle::Running);
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "id": 9, "method": "shutdown"}));
+        assert_eq!(session.lifecycle(), Lifecycle::ShuttingDown);
+        session.record_editor_message(&json!({"jsonrpc": "2.0", "method": "exit"}));
+        assert_eq!(session.lifecycle(), Lifecycle::Exited);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_finish_reports_status_and_kills_after_grace() {
+        let mut server = ServerProcess::new("sh".to_string(), vec!["-c".to_string(), "exit 3".to_string()]);
+        server.connect().await.unwrap();
+        let status = server.finish(Duration::from_secs(10)).await.unwrap().unwrap();
+        assert_eq!(exit_code(status), 3);
+
+        let mut server = ServerProcess::new("sleep".to_string(), vec!["10".to_string()]);
+        server.connect().await.unwrap();
+        let status = server.finish(Duration::from_millis(50)).await.unwrap().unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGKILL);
+
+        let mut server = ServerProcess::new("sleep".to_string(), vec!["10".to_string()]);
+        server.connect().await.unwrap();
+        server.signal(libc::SIGTERM);
+        let status = server.finish(Duration::from_secs(10)).await.unwrap().unwrap();
+        assert_eq!(exit_code(status), 128 + libc::SIGTERM);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::default();
+        let quick = Duration::from_secs(1);
+        let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay(quick)).collect();
+        assert_eq!(delays.len(), MAX_RESTARTS as usize);
+        assert_eq!(delays[0], FIRST_RESTART_DELAY);
+        assert_eq!(delays[2], FIRST_RESTART_DELAY * 4);
+
+        // A server that ran for a while starts over.
+        assert_eq!(backoff.next_delay(STABLE_UPTIME), Some(FIRST_RESTART_DELAY));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::config::Config;
+use crate::errors::Catalog;
+use crate::proxy::Replayer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+
+/// Lines of context shown around each change in a replay diff.
+const CONTEXT: usize = 2;
+
+/// Above this many line pairs, a changed message is shown whole rather than diffed.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Entries waiting for the writer; beyond this, new ones are dropped.
+const QUEUE_SIZE: usize = 4096;
+
+/// Which way a traced message went.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    #[serde(rename = "editor->proxy")]
+    EditorToProxy,
+    #[serde(rename = "proxy->server")]
+    ProxyToServer,
+    #[serde(rename = "server->proxy")]
+    ServerToProxy,
+    #[serde(rename = "proxy->editor")]
+    ProxyToEditor,
+}
+
+/// One line of a trace file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// UTC, with milliseconds
+    pub time: String,
+    pub direction: Direction,
+    /// Number of the editor connection under `--listen`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<u64>,
+    /// The message, or its text if it is not JSON
+    pub message: Value,
+}
+
+/// What the trace writer task is asked to do.
+enum Command {
+    Write(Vec<u8>),
+    /// Flush everything sent so far, then reply
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes messages to a `--trace` file as JSON lines; records nothing when
+/// not tracing. Clones write to the same file.
+///
+/// Entries go over a channel to a writer task, so recording never blocks
+/// the proxy on disk I/O. When the disk cannot keep up and the queue is full,
+/// entries are dropped and counted rather than buffered without bound.
+#[derive(Clone, Default)]
+pub struct Trace {
+    writer: Option<mpsc::Sender<Command>>,
+    dropped: Arc<AtomicU64>,
+    session: Option<u64>,
+}
+
+impl Trace {
+    /// Starts a trace at `path`, replacing an earlier one.
+    pub async fn create(path: &Path) -> std::io::Result<Self> {
+        let file = tokio::fs::File::create(path).await?;
+        let (sender, commands) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(write_entries(file, commands));
+        Ok(Self {
+            writer: Some(sender),
+            dropped: Arc::default(),
+            session: None,
+        })
+    }
+
+    /// The same trace, with entries marked as belonging to `session`.
+    pub fn for_session(&self, session: u64) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            dropped: self.dropped.clone(),
+            session: Some(session),
+        }
+    }
+
+    pub fn record(&self, direction: Direction, message: &[u8]) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let entry = Entry {
+            time: timestamp(SystemTime::now()),
+            direction,
+            session: self.session,
+            message: serde_json::from_slice(message)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(message).into_owned())),
+        };
+        let Ok(mut line) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        line.push(b'\n');
+        if let Err(mpsc::error::TrySendError::Full(_)) = writer.try_send(Command::Write(line)) {
+            if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                eprintln!("ts-error-translator-proxy: the trace file cannot keep up; dropping entries");
+            }
+        }
+    }
+
+    /// Entries dropped so far because the writer could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits until everything recorded so far is in the file, and says how
+    /// many entries were dropped, if any.
+    pub async fn flush(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let (done, flushed) = oneshot::channel();
+        if writer.send(Command::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+        let dropped = self.dropped();
+        if dropped > 0 {
+            eprintln!("ts-error-translator-proxy: {} trace entries were dropped", dropped);
+        }
+    }
+}
+
+/// The writer task: appends entries, flushing whenever it catches up.
+/// A write error is reported once and turns off the trace.
+async fn write_entries(file: tokio::fs::File, mut commands: mpsc::Receiver<Command>) {
+    let mut file = BufWriter::new(file);
+    let mut failed = false;
+    while let Some(command) = commands.recv().await {
+        let result = match command {
+            Command::Write(_) if failed => Ok(()),
+            Command::Write(line) => file.write_all(&line).await,
+            Command::Flush(done) => {
+                let result = file.flush().await;
+                let _ = done.send(());
+                result
+            }
+        };
+        let result = match result {
+            Ok(()) if !failed && commands.is_empty() => file.flush().await,
+            result => result,
+        };
+        if let Err(e) = result {
+            if !failed {
+                eprintln!("ts-error-translator-proxy: cannot write trace: {}", e);
+            }
+            failed = true;
+        }
+    }
+    if !failed {
+        let _ = file.flush().await;
+    }
+}
+
+/// Parses a trace file into its entries, with their line numbers.
+pub fn parse_trace(source: &str) -> Result<Vec<(usize, Entry)>, String> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map(|entry| (i + 1, entry))
+                .map_err(|e| format!("trace line {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+/// Feeds the editor's and the server's messages from `entries` through the
+/// proxy, printing how each server message is changed for the editor.
+///
+/// Returns the number of server messages and how many of them changed.
+pub fn replay<W: Write>(
+    entries: &[(usize, Entry)],
+    catalog: &Catalog,
+    config: &Config,
+    output: &mut W,
+) -> std::io::Result<(usize, usize)> {
+    let mut sessions: HashMap<Option<u64>, Replayer> = HashMap::new();
+    let (mut total, mut changed) = (0, 0);
+
+    for (line, entry) in entries {
+        let replayer = sessions
+            .entry(entry.session)
+            .or_insert_with(|| Replayer::new(catalog.clone(), config.clone()));
+        let message = match &entry.message {
+            Value::String(text) => text.as_bytes().to_vec(),
+            message => serde_json::to_vec(message)?,
+        };
+        match entry.direction {
+            Direction::EditorToProxy => replayer.editor_message(&message),
+            Direction::ServerToProxy => {
+                total += 1;
+                let after = replayer.server_message(&message);
+                let after: Value = serde_json::from_slice(&after).unwrap_or(Value::Null);
+                if after == entry.message {
+                    continue;
+                }
+                changed += 1;
+                writeln!(output, "line {} ({}): {}", line, entry.time, describe(&entry.message))?;
+                let before = serde_json::to_string_pretty(&entry.message)?;
+                let after = serde_json::to_string_pretty(&after)?;
+                write_diff(&before, &after, output)?;
+                writeln!(output)?;
+            }
+            Direction::ProxyToServer | Direction::ProxyToEditor => {}
+        }
+    }
+    Ok((total, changed))
+}
+
+/// The method of a notification or request, or which request a response answers.
+fn describe(message: &Value) -> String {
+    match (message.get("method").and_then(Value::as_str), message.get("id")) {
+        (Some(method), _) => method.to_string(),
+        (None, Some(id)) => format!("response to {}", id),
+        (None, None) => "message".to_string(),
+    }
+}
+
+/// Prints the lines that differ between `before` and `after`, `-` and `+`
+/// prefixed, with some unchanged lines around them.
+pub fn write_diff<W: Write>(before: &str, after: &str, output: &mut W) -> std::io::Result<()> {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    let lines = diff_lines(&before, &after);
+
+    let changes: Vec<usize> = (0..lines.len()).filter(|&i| lines[i].0 != ' ').collect();
+    let mut shown_until = 0;
+    for (i, (tag, text)) in lines.iter().enumerate() {
+        let near_change = changes
+            .iter()
+            .any(|&change| i + CONTEXT >= change && i <= change + CONTEXT);
+        if !near_change {
+            continue;
+        }
+        if i > shown_until {
+            writeln!(output, "  ...")?;
+        }
+        writeln!(output, "{} {}", tag, text)?;
+        shown_until = i + 1;
+    }
+    if shown_until < lines.len() {
+        writeln!(output, "  ...")?;
+    }
+    Ok(())
+}
+
+/// Lines of `before` and `after`, tagged `' '` when kept, `'-'` when removed
+/// and `'+'` when added, from their longest common subsequence.
+fn diff_lines<'a>(before: &[&'a str], after: &[&'a str]) -> Vec<(char, &'a str)> {
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &before[prefix..before.len() - suffix];
+    let new = &after[prefix..after.len() - suffix];
+
+    let mut lines: Vec<(char, &str)> = before[..prefix].iter().map(|line| (' ', *line)).collect();
+    if (old.len() + 1) * (new.len() + 1) > MAX_DIFF_CELLS {
+        lines.extend(old.iter().map(|line| ('-', *line)));
+        lines.extend(new.iter().map(|line| ('+', *line)));
+    } else {
+        // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+        let width = new.len() + 1;
+        let mut common = vec![0u32; (old.len() + 1) * width];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i * width + j] = if old[i] == new[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() && j < new.len() {
+            if old[i] == new[j] {
+                lines.push((' ', old[i]));
+                i += 1;
+                j += 1;
+            } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+                lines.push(('-', old[i]));
+                i += 1;
+            } else {
+                lines.push(('+', new[j]));
+                j += 1;
+            }
+        }
+        lines.extend(old[i..].iter().map(|line| ('-', *line)));
+        lines.extend(new[j..].iter().map(|line| ('+', *line)));
+    }
+    lines.extend(before[before.len() - suffix..].iter().map(|line| (' ', *line)));
+    lines
+}
+
+/// Formats `time` as an RFC 3339 UTC timestamp with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Days since 1970-01-01 to a civil date, counting years from March so
+    // that leap days come last (after Howard Hinnant's `civil_from_days`).
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ERRORS;
+    use crate::TranslationMode;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(timestamp(time), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[tokio::test]
+    async fn test_records_and_parses_entries() {
+        let path = std::env::temp_dir().join(format!("ts-error-translator-trace-{}.jsonl", std::process::id()));
+        let trace = Trace::create(&path).await.unwrap();
+        trace.record(Direction::EditorToProxy, br#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#);
+        trace.for_session(3).record(Direction::ServerToProxy, b"not json");
+        trace.flush().await;
+
+        let entries = parse_trace(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].1.direction, Direction::EditorToProxy);
+        assert_eq!(entries[0].1.session, None);
+        assert_eq!(entries[0].1.message["method"], "initialize");
+        assert_eq!(entries[1].1.session, Some(3));
+        assert_eq!(entries[1].1.message, "not json");
+
+        assert_eq!(parse_trace("\n{}\n").unwrap_err().split(':').next(), Some("trace line 2"));
+    }
+
+    #[tokio::test]
+    async fn test_drops_entries_when_queue_is_full() {
+        let path = std::env::temp_dir().join(format!("ts-error-translator-trace-full-{}.jsonl", std::process::id()));
+        let trace = Trace::create(&path).await.unwrap();
+        // The writer task gets no chance to run until the test awaits.
+        for _ in 0..QUEUE_SIZE + 10 {
+            trace.record(Direction::ServerToProxy, b"{}");
+        }
+        assert_eq!(trace.dropped(), 10);
+        trace.flush().await;
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.lines().count(), QUEUE_SIZE);
+    }
+
+    #[test]
+    fn test_diff_shows_changes_with_context() {
+        let before = "a\nb\nc\nd\ne\nf\ng\nh";
+        let after = "a\nb\nc\nd\nE\nf\ng\nh";
+        let mut output = Vec::new();
+        write_diff(before, after, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "  ...\n  c\n  d\n- e\n+ E\n  f\n  g\n  ...\n"
+        );
+    }
+
+    #[test]
+    fn test_replay_diffs_translated_diagnostics() {
+        let publish = |message: &str| {
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {"uri": "file:///a.ts", "diagnostics": [{"code": 2304, "message": message}]}
+            })
+        };
+        let source = [
+            serde_json::json!({"time": "t", "direction": "editor->proxy", "message": {"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}}),
+            serde_json::json!({"time": "t", "direction": "server->proxy", "message": {"jsonrpc": "2.0", "id": 1, "result": {"capabilities": {}}}}),
+            serde_json::json!({"time": "t", "direction": "server->proxy", "message": publish("Cannot find name 'foo'.")}),
+            serde_json::json!({"time": "t", "direction": "proxy->editor", "message": publish("ignored")}),
+        ]
+        .map(|entry| entry.to_string())
+        .join("\n");
+        let entries = parse_trace(&source).unwrap();
+
+        let mut output = Vec::new();
+        let config = Config::new(TranslationMode::Replace);
+        let (total, changed) = replay(&entries, &ERRORS, &config, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // The initialize response gains the explain command; the diagnostic is translated.
+        assert_eq!((total, changed), (2, 2));
+        assert!(output.contains("line 3 (t): textDocument/publishDiagnostics"));
+        assert!(output.contains("\n-         \"message\": \"Cannot find name 'foo'.\"\n+         \"message\": \"● I can't find 'foo'"));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::errors::{extract_params, substitute_params, ERRORS};
+use crate::errors::{substitute_params, Catalog};
+use crate::types::{describe_difference, simplify, TypeLimits};
 use crate::TranslationMode;
 use regex::Regex;
 use std::borrow::Cow;
@@ -7,6 +8,27 @@ use std::sync::LazyLock;
 static TS_CODE_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?i)\bts(\d+)\b").unwrap());
 
+static TEMPLATE_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{(original|translation|code|link|bullet)\}").unwrap());
+
+/// Named documentation sites for `{link}` and `codeDescription.href`.
+const DOCS_PRESETS: &[(&str, &str)] = &[
+    (
+        "upstream",
+        "https://github.com/mattpocock/ts-error-translator/blob/main/packages/engine/errors/{code}.md",
+    ),
+    ("typescript.tv", "https://typescript.tv/errors/#ts{code}"),
+];
+
+/// Named templates for editors that render diagnostics differently.
+const PRESETS: &[(&str, &str)] = &[
+    // Helix shows the message as virtual text that is cut off at the window
+    // edge, so the translation goes first and everything stays on one line.
+    ("helix", "{bullet} {translation} [TS{code}]"),
+    // Zed shows the full message in a popover and keeps line breaks.
+    ("zed", "{translation}\n{original}"),
+];
+
 pub fn extract_error_code(message: &str) -> Option<u32> {
     TS_CODE_REGEX
         .captures(message)
@@ -14,38 +36,185 @@ pub fn extract_error_code(message: &str) -> Option<u32> {
         .and_then(|m| m.as_str().parse().ok())
 }
 
-pub fn translate_message<'a>(original: &'a str, code: Option<i64>, mode: TranslationMode) -> Cow<'a, str> {
+/// How translations are rendered into the message text.
+#[derive(Clone, Debug)]
+pub struct Style {
+    /// Marker placed before each translation
+    pub bullet: String,
+    /// Layout of each translated line, overriding the mode's own; see [`Style::set_template`]
+    pub template: Option<String>,
+    /// Documentation URL with `{code}` standing for the error code; `None` for no links
+    pub link: Option<String>,
+    /// Limits for type text substituted into translations
+    pub types: TypeLimits,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            bullet: "●".to_string(),
+            template: None,
+            link: Some(DOCS_PRESETS[1].1.to_string()),
+            types: TypeLimits::default(),
+        }
+    }
+}
+
+impl Style {
+    /// Sets the template from a preset name (`helix`, `zed`), a template using
+    /// `{original}`, `{translation}`, `{code}`, `{link}` and `{bullet}`, or
+    /// `default`/an empty string for the mode's own layout.
+    pub fn set_template(&mut self, template: &str) {
+        self.template = match template {
+            "" | "default" => None,
+            name => Some(
+                PRESETS
+                    .iter()
+                    .find(|(preset, _)| *preset == name)
+                    .map_or(name, |(_, template)| template)
+                    .to_string(),
+            ),
+        };
+    }
+
+    /// Sets the documentation URL from a site name (`upstream`, `typescript.tv`),
+    /// a URL template such as `https://wiki/{code}`, or `none`.
+    pub fn set_docs_url(&mut self, docs_url: &str) {
+        self.link = match docs_url {
+            "" | "none" => None,
+            name => Some(
+                DOCS_PRESETS
+                    .iter()
+                    .find(|(preset, _)| *preset == name)
+                    .map_or(name, |(_, url)| url)
+                    .to_string(),
+            ),
+        };
+    }
+
+    /// The documentation URL for `code`, if links are enabled.
+    pub fn link_for(&self, code: u32) -> Option<String> {
+        self.link.as_ref().map(|link| link.replace("{code}", &code.to_string()))
+    }
+
+    fn render(&self, mode: TranslationMode, original: &str, translation: &str, code: u32) -> String {
+        let template = self.template.as_deref().unwrap_or(match mode {
+            TranslationMode::Append => "{original}  {bullet} {translation}",
+            TranslationMode::Prepend => "{bullet} {translation}  {original}",
+            TranslationMode::Newline => "{original}\n{bullet} {translation}",
+            TranslationMode::Replace | TranslationMode::Related | TranslationMode::Diagnostic => {
+                "{bullet} {translation}"
+            }
+        });
+        TEMPLATE_PLACEHOLDER
+            .replace_all(template, |caps: &regex::Captures| match &caps[1] {
+                "original" => original.to_string(),
+                "translation" => translation.to_string(),
+                "code" => code.to_string(),
+                "link" => self.link_for(code).unwrap_or_default(),
+                _ => self.bullet.clone(),
+            })
+            .into_owned()
+    }
+}
+
+/// Translates a diagnostic message, including each level of a TypeScript
+/// message chain (continuation lines indented under the headline).
+///
+/// Modes that leave the message itself alone render like `Replace`; the
+/// caller decides where the text goes.
+pub fn translate_message<'a>(
+    catalog: &Catalog,
+    original: &'a str,
+    code: Option<i64>,
+    mode: TranslationMode,
+    style: &Style,
+) -> Cow<'a, str> {
     let error_code = code
         .map(|c| c as u32)
         .or_else(|| extract_error_code(original));
 
-    let Some(error_code) = error_code else {
-        return Cow::Borrowed(original);
-    };
+    let mut translated_any = false;
+    let mut lines = Vec::new();
+
+    for (depth, line) in original.split('\n').enumerate() {
+        let text = line.trim_start();
+        let indent = &line[..line.len() - text.len()];
+
+        // Only the headline carries the diagnostic code; nested levels are matched by text.
+        let translation = match (depth, error_code) {
+            (0, Some(error_code)) => translate_with_code(catalog, text, error_code, &style.types).map(|t| (error_code, t)),
+            _ => translate_by_pattern(catalog, text, &style.types),
+        };
 
-    let Some(info) = ERRORS.get(&error_code) else {
+        match translation {
+            Some((code, translation)) => {
+                translated_any = true;
+                let rendered = style.render(mode, text, &translation, code);
+                for rendered_line in rendered.split('\n') {
+                    lines.push(format!("{}{}", indent, rendered_line));
+                }
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    if !translated_any {
         return Cow::Borrowed(original);
-    };
+    }
+    Cow::Owned(lines.join("\n"))
+}
 
-    let translation = match extract_params(&info.pattern, original) {
-        Some(params) => substitute_params(info.message, &params),
-        None => info.message.to_string(),
-    };
+/// The long-form markdown explanation for a diagnostic, with parameters from
+/// its headline filled in. `None` if the code has no `body` in the catalog.
+pub fn explain_message(catalog: &Catalog, original: &str, code: u32) -> Option<String> {
+    let info = catalog.get(code)?;
+    let body = info.body.as_deref()?;
+    let headline = original.lines().next().unwrap_or_default().trim();
+    Some(match info.params(headline) {
+        Some(params) => substitute_params(body, &params),
+        None => body.to_string(),
+    })
+}
 
-    Cow::Owned(match mode {
-        TranslationMode::Append => format!("{}  ● {}", original, translation),
-        TranslationMode::Replace => format!("● {}", translation),
+fn translate_with_code(catalog: &Catalog, text: &str, code: u32, limits: &TypeLimits) -> Option<String> {
+    let info = catalog.get(code)?;
+    Some(match info.params(text) {
+        Some(params) => fill_translation(&info.message, code, &params, limits),
+        None => info.message.clone(),
     })
 }
 
+fn translate_by_pattern(catalog: &Catalog, text: &str, limits: &TypeLimits) -> Option<(u32, String)> {
+    let (code, info, params) = catalog.find_match(text)?;
+    Some((code, fill_translation(&info.message, code, &params, limits)))
+}
+
+/// Substitutes simplified parameters and, for type mismatches between two
+/// object types, notes the property that differs.
+fn fill_translation(message: &str, code: u32, params: &[&str], limits: &TypeLimits) -> String {
+    let simplified: Vec<String> = params.iter().map(|param| simplify(param, limits)).collect();
+    let simplified: Vec<&str> = simplified.iter().map(String::as_str).collect();
+    let mut translation = substitute_params(message, &simplified);
+
+    if limits.highlight_difference && matches!(code, 2322 | 2345) && params.len() == 2 {
+        if let Some(difference) = describe_difference(params[0], params[1], limits) {
+            translation.push(' ');
+            translation.push_str(&difference);
+        }
+    }
+    translation
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::errors::ERRORS;
 
     #[test]
     fn test_translate_with_params() {
         let msg = "Property 'foo' does not exist on type 'Bar'.";
-        let result = translate_message(msg, Some(2339), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(2339), TranslationMode::Append, &Style::default());
         assert!(result.contains(msg));
         assert!(result.contains("You're trying to access 'foo' on an object that doesn't contain it."));
     }
@@ -53,7 +222,7 @@ mod tests {
     #[test]
     fn test_translate_replace_with_params() {
         let msg = "Property 'foo' does not exist on type 'Bar'.";
-        let result = translate_message(msg, Some(2339), TranslationMode::Replace);
+        let result = translate_message(&ERRORS, msg, Some(2339), TranslationMode::Replace, &Style::default());
         assert!(!result.contains(msg));
         assert!(result.contains("You're trying to access 'foo' on an object that doesn't contain it."));
     }
@@ -61,42 +230,150 @@ mod tests {
     #[test]
     fn test_translate_type_mismatch() {
         let msg = "Type 'string' is not assignable to type 'number'.";
-        let result = translate_message(msg, Some(2322), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(2322), TranslationMode::Append, &Style::default());
         assert!(result.contains("I was expecting a type matching 'number' but instead you passed 'string'."));
     }
 
     #[test]
     fn test_translate_argument_count() {
         let msg = "Expected 2 arguments, but got 3.";
-        let result = translate_message(msg, Some(2554), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(2554), TranslationMode::Append, &Style::default());
         assert!(result.contains("This function needs 2 argument(s), but you're passing 3."));
     }
 
     #[test]
     fn test_unknown_code_unchanged() {
         let msg = "Some unknown error";
-        let result = translate_message(msg, Some(99999), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(99999), TranslationMode::Append, &Style::default());
         assert_eq!(result, msg);
     }
 
     #[test]
     fn test_extracts_code_from_message_text() {
         let msg = "error TS2339: Property 'x' does not exist on type 'Y'.";
-        let result = translate_message(msg, None, TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, None, TranslationMode::Append, &Style::default());
         assert!(result.contains("●"));
     }
 
     #[test]
     fn test_cannot_find_name() {
         let msg = "Cannot find name 'myVariable'.";
-        let result = translate_message(msg, Some(2304), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(2304), TranslationMode::Append, &Style::default());
         assert!(result.contains("I can't find 'myVariable' - it might not be imported or defined."));
     }
 
     #[test]
     fn test_module_no_export() {
         let msg = "Module './utils' has no exported member 'helper'.";
-        let result = translate_message(msg, Some(2305), TranslationMode::Append);
+        let result = translate_message(&ERRORS, msg, Some(2305), TranslationMode::Append, &Style::default());
         assert!(result.contains("'helper' is not exported from './utils'."));
     }
+
+    #[test]
+    fn test_translates_message_chain() {
+        let msg = "Type '{ id: string; }' is not assignable to type 'User'.\n  Types of property 'id' are incompatible.\n    Type 'string' is not assignable to type 'number'.";
+        let result = translate_message(&ERRORS, msg, Some(2322), TranslationMode::Replace, &Style::default());
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "● I was expecting a type matching 'User' but instead you passed '{ id: string; }'."
+        );
+        assert_eq!(lines[1], "  ● Types of property 'id' are incompatible.");
+        assert_eq!(
+            lines[2],
+            "    ● I was expecting a type matching 'number' but instead you passed 'string'."
+        );
+    }
+
+    #[test]
+    fn test_message_chain_append_keeps_original_levels() {
+        let msg = "Argument of type 'X' is not assignable to parameter of type 'Y'.\n  Type 'string' is not assignable to type 'number'.";
+        let result = translate_message(&ERRORS, msg, Some(2345), TranslationMode::Append, &Style::default());
+        let lines: Vec<&str> = result.lines().collect();
+        assert!(lines[0].starts_with("Argument of type 'X'"));
+        assert!(lines[1].starts_with("  Type 'string' is not assignable to type 'number'.  ● "));
+    }
+
+    #[test]
+    fn test_unknown_chain_unchanged() {
+        let msg = "Some unknown error\n  with an unknown elaboration";
+        let result = translate_message(&ERRORS, msg, Some(99999), TranslationMode::Append, &Style::default());
+        assert!(matches!(result, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_explain_fills_params_from_headline() {
+        let msg = "Type 'string' is not assignable to type 'number'.\n  Some elaboration.";
+        let body = explain_message(&ERRORS, msg, 2322).unwrap();
+        assert!(body.contains("`string` somewhere that expects `number`"));
+    }
+
+    #[test]
+    fn test_explain_without_body() {
+        assert!(explain_message(&ERRORS, "Identifier expected.", 1003).is_none());
+    }
+
+    #[test]
+    fn test_prepend_and_newline_modes() {
+        let msg = "Cannot find name 'foo'.";
+        let translation = "● I can't find 'foo' - it might not be imported or defined.";
+        let result = translate_message(&ERRORS, msg, Some(2304), TranslationMode::Prepend, &Style::default());
+        assert_eq!(result, format!("{}  {}", translation, msg));
+
+        let msg = "Type 'A' is not assignable to type 'B'.\n  Type 'string' is not assignable to type 'number'.";
+        let result = translate_message(&ERRORS, msg, Some(2322), TranslationMode::Newline, &Style::default());
+        assert_eq!(
+            result,
+            "Type 'A' is not assignable to type 'B'.\n● I was expecting a type matching 'B' but instead you passed 'A'.\n  Type 'string' is not assignable to type 'number'.\n  ● I was expecting a type matching 'number' but instead you passed 'string'."
+        );
+    }
+
+    #[test]
+    fn test_custom_bullet() {
+        let style = Style {
+            bullet: "→".to_string(),
+            ..Style::default()
+        };
+        let result = translate_message(&ERRORS, "Cannot find name 'x'.", Some(2304), TranslationMode::Replace, &style);
+        assert_eq!(result, "→ I can't find 'x' - it might not be imported or defined.");
+    }
+
+    #[test]
+    fn test_simplifies_large_types() {
+        let msg = "Type '{ id: string; meta: { tags: { name: string; }[]; }; }' is not assignable to type '{ id: number; meta: { tags: { name: string; }[]; }; }'.";
+        let result = translate_message(&ERRORS, msg, Some(2322), TranslationMode::Replace, &Style::default());
+        assert_eq!(
+            result,
+            "● I was expecting a type matching '{ id: number; meta: { tags: { ... }[]; }; }' but instead you passed '{ id: string; meta: { tags: { ... }[]; }; }'. The difference is in 'id': 'string' instead of 'number'."
+        );
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let mut style = Style::default();
+        style.set_template("[TS{code}] {translation} (original: {original}) {link}");
+        let result = translate_message(&ERRORS, "Cannot find name 'x'.", Some(2304), TranslationMode::Replace, &style);
+        assert_eq!(
+            result,
+            "[TS2304] I can't find 'x' - it might not be imported or defined. (original: Cannot find name 'x'.) https://typescript.tv/errors/#ts2304"
+        );
+    }
+
+    #[test]
+    fn test_template_applies_per_chain_level() {
+        let mut style = Style::default();
+        style.set_template("zed");
+        let msg = "Type 'A' is not assignable to type 'B'.\n  Type 'string' is not assignable to type 'number'.";
+        let result = translate_message(&ERRORS, msg, Some(2322), TranslationMode::Replace, &style);
+        assert_eq!(
+            result,
+            "I was expecting a type matching 'B' but instead you passed 'A'.\nType 'A' is not assignable to type 'B'.\n  I was expecting a type matching 'number' but instead you passed 'string'.\n  Type 'string' is not assignable to type 'number'."
+        );
+
+        // Braces inside the message are not placeholders.
+        style.set_template("helix");
+        let result = translate_message(&ERRORS, "Cannot find name '{code}'.", Some(2304), TranslationMode::Replace, &style);
+        assert_eq!(result, "● I can't find '{code}' - it might not be imported or defined. [TS2304]");
+    }
 }
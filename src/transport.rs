@@ -0,0 +1,225 @@
+use std::fmt;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+pub type Reader = Box<dyn AsyncRead + Unpin + Send>;
+pub type Writer = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Where to listen for editors, or where to reach a running language server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    /// `host:port`
+    Tcp(String),
+    /// A Unix domain socket, or a named pipe (`\\.\pipe\name`) on Windows
+    Local(PathBuf),
+}
+
+impl Address {
+    /// Parses `PORT` (on localhost), `HOST:PORT`, or a socket path: anything
+    /// containing a path separator, or prefixed with `unix:`.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            return Some(Address::Local(PathBuf::from(path)));
+        }
+        if value.contains(['/', '\\']) {
+            return Some(Address::Local(PathBuf::from(value)));
+        }
+        if value.parse::<u16>().is_ok() {
+            return Some(Address::Tcp(format!("127.0.0.1:{}", value)));
+        }
+        let (host, port) = value.rsplit_once(':')?;
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return None;
+        }
+        Some(Address::Tcp(value.to_string()))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Local(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Connects to a language server listening at `address`.
+pub async fn connect(address: &Address) -> std::io::Result<(Reader, Writer)> {
+    match address {
+        Address::Tcp(address) => Ok(split_tcp(TcpStream::connect(address).await?)),
+        #[cfg(unix)]
+        Address::Local(path) => {
+            let (reader, writer) = tokio::net::UnixStream::connect(path).await?.into_split();
+            Ok((Box::new(reader), Box::new(writer)))
+        }
+        #[cfg(windows)]
+        Address::Local(path) => {
+            let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+            let (reader, writer) = tokio::io::split(pipe);
+            Ok((Box::new(reader), Box::new(writer)))
+        }
+    }
+}
+
+fn split_tcp(stream: TcpStream) -> (Reader, Writer) {
+    // Messages are written whole; don't hold them back waiting for more.
+    let _ = stream.set_nodelay(true);
+    let (reader, writer) = stream.into_split();
+    (Box::new(reader), Box::new(writer))
+}
+
+/// Accepts editor connections.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+    #[cfg(windows)]
+    Pipe {
+        path: PathBuf,
+        /// The instance the next editor connects to
+        next: tokio::net::windows::named_pipe::NamedPipeServer,
+    },
+}
+
+impl Listener {
+    pub async fn bind(address: &Address) -> std::io::Result<Self> {
+        match address {
+            Address::Tcp(address) => Ok(Listener::Tcp(TcpListener::bind(address).await?)),
+            #[cfg(unix)]
+            Address::Local(path) => Ok(Listener::Unix(bind_unix(path)?, path.clone())),
+            #[cfg(windows)]
+            Address::Local(path) => {
+                let next = tokio::net::windows::named_pipe::ServerOptions::new()
+                    .first_pipe_instance(true)
+                    .create(path)?;
+                Ok(Listener::Pipe {
+                    path: path.clone(),
+                    next,
+                })
+            }
+        }
+    }
+
+    pub async fn accept(&mut self) -> std::io::Result<(Reader, Writer)> {
+        match self {
+            Listener::Tcp(listener) => Ok(split_tcp(listener.accept().await?.0)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let (reader, writer) = listener.accept().await?.0.into_split();
+                Ok((Box::new(reader), Box::new(writer)))
+            }
+            #[cfg(windows)]
+            Listener::Pipe { path, next } => {
+                next.connect().await?;
+                let fresh = tokio::net::windows::named_pipe::ServerOptions::new().create(&*path)?;
+                let (reader, writer) = tokio::io::split(std::mem::replace(next, fresh));
+                Ok((Box::new(reader), Box::new(writer)))
+            }
+        }
+    }
+
+    /// The address editors can connect to, with the actual port when bound to port 0.
+    pub fn local_address(&self) -> Address {
+        match self {
+            Listener::Tcp(listener) => Address::Tcp(
+                listener
+                    .local_addr()
+                    .map(|address| address.to_string())
+                    .unwrap_or_default(),
+            ),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => Address::Local(path.clone()),
+            #[cfg(windows)]
+            Listener::Pipe { path, .. } => Address::Local(path.clone()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Binds a Unix socket, replacing a stale socket an earlier run left behind.
+/// Anything else at `path` is left alone.
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match tokio::net::UnixListener::bind(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse
+            && std::fs::symlink_metadata(path)?.file_type().is_socket()
+            && std::os::unix::net::UnixStream::connect(path).is_err() =>
+        {
+            std::fs::remove_file(path)?;
+            tokio::net::UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{read_message, write_message, DEFAULT_MAX_MESSAGE_SIZE};
+    use tokio::io::BufReader;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(Address::parse("9000"), Some(Address::Tcp("127.0.0.1:9000".to_string())));
+        assert_eq!(Address::parse("0.0.0.0:9000"), Some(Address::Tcp("0.0.0.0:9000".to_string())));
+        assert_eq!(Address::parse("[::1]:9000"), Some(Address::Tcp("[::1]:9000".to_string())));
+        assert_eq!(Address::parse("/tmp/lsp.sock"), Some(Address::Local(PathBuf::from("/tmp/lsp.sock"))));
+        assert_eq!(Address::parse("unix:lsp.sock"), Some(Address::Local(PathBuf::from("lsp.sock"))));
+        assert_eq!(Address::parse(r"\\.\pipe\vtsls"), Some(Address::Local(PathBuf::from(r"\\.\pipe\vtsls"))));
+        assert_eq!(Address::parse("localhost"), None);
+        assert_eq!(Address::parse(":9000"), None);
+        assert_eq!(Address::parse("host:99999"), None);
+    }
+
+    async fn round_trip(address: &Address) {
+        let mut listener = Listener::bind(address).await.unwrap();
+        let address = listener.local_address();
+        let (accepted, connected) = tokio::join!(listener.accept(), connect(&address));
+        let (server_reader, _server_writer) = accepted.unwrap();
+        let (_client_reader, mut client_writer) = connected.unwrap();
+
+        write_message(&mut client_writer, b"{}").await.unwrap();
+        let mut server_reader = BufReader::new(server_reader);
+        assert_eq!(read_message(&mut server_reader, DEFAULT_MAX_MESSAGE_SIZE).await.unwrap().unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_tcp_round_trip() {
+        round_trip(&Address::Tcp("127.0.0.1:0".to_string())).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_round_trip_replaces_stale_socket() {
+        let path = std::env::temp_dir().join(format!("ts-error-translator-{}.sock", std::process::id()));
+        // A socket file nobody listens on, as a crashed run leaves behind.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        round_trip(&Address::Local(path.clone())).await;
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_keeps_other_files() {
+        let path = std::env::temp_dir().join(format!("ts-error-translator-{}.not-a-sock", std::process::id()));
+        std::fs::write(&path, "keep me").unwrap();
+
+        assert!(Listener::bind(&Address::Local(path.clone())).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use crate::errors::Catalog;
+use crate::translator::{translate_message, Style};
+use crate::TranslationMode;
+use regex::Regex;
+use std::io::{BufRead, Write};
+use std::sync::LazyLock;
+
+/// `file(line,col): error TS1234: message` (`--pretty false`) or
+/// `file:line:col - error TS1234: message` (`--pretty`), with the location optional.
+static DIAGNOSTIC_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:.+?(?:\(\d+,\d+\)|:\d+:\d+)(?::| -) )?(?:error|warning|message) TS(\d+): (.*)$").unwrap()
+});
+
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+const TRANSLATION_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// A diagnostic whose message is still being read: its headline plus any
+/// indented continuation lines of the message chain.
+struct Block {
+    code: u32,
+    message: String,
+    colored: bool,
+}
+
+/// Copies `tsc` output from `input` to `output`, printing the translation of
+/// each diagnostic in `style` right after its (possibly multi-line) message.
+pub fn annotate<R: BufRead, W: Write>(catalog: &Catalog, style: &Style, input: R, mut output: W) -> std::io::Result<()> {
+    let mut block: Option<Block> = None;
+
+    for line in input.lines() {
+        let line = line?;
+        let plain = ANSI_ESCAPE.replace_all(&line, "");
+
+        if let Some(caps) = DIAGNOSTIC_LINE.captures(&plain) {
+            if let Some(done) = block.take() {
+                write_translation(catalog, style, &done, &mut output)?;
+            }
+            writeln!(output, "{}", line)?;
+            block = Some(Block {
+                code: caps[1].parse().unwrap_or(0),
+                message: caps[2].to_string(),
+                colored: plain.len() != line.len(),
+            });
+            continue;
+        }
+
+        let continues = plain.starts_with(' ') && !plain.trim().is_empty();
+        match &mut block {
+            Some(current) if continues => {
+                current.message.push('\n');
+                current.message.push_str(&plain);
+            }
+            _ => {
+                if let Some(done) = block.take() {
+                    write_translation(catalog, style, &done, &mut output)?;
+                }
+            }
+        }
+        writeln!(output, "{}", line)?;
+    }
+
+    if let Some(done) = block.take() {
+        write_translation(catalog, style, &done, &mut output)?;
+    }
+    output.flush()
+}
+
+/// Prints the translated levels of a message chain, indented under it.
+fn write_translation<W: Write>(catalog: &Catalog, style: &Style, block: &Block, output: &mut W) -> std::io::Result<()> {
+    let translated = translate_message(
+        catalog,
+        &block.message,
+        Some(block.code as i64),
+        TranslationMode::Replace,
+        style,
+    );
+    for (original, translated) in block.message.lines().zip(translated.lines()) {
+        if original == translated {
+            continue;
+        }
+        if block.colored {
+            writeln!(output, "  {}{}{}", TRANSLATION_COLOR, translated, RESET)?;
+        } else {
+            writeln!(output, "  {}", translated)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ERRORS;
+
+    fn run(input: &str) -> String {
+        let mut output = Vec::new();
+        annotate(&ERRORS, &Style::default(), input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_plain_format() {
+        let output = run("src/a.ts(3,7): error TS2304: Cannot find name 'foo'.\nsrc/b.ts(1,1): error TS99999: Unknown.\n");
+        assert_eq!(
+            output,
+            "src/a.ts(3,7): error TS2304: Cannot find name 'foo'.\n  ● I can't find 'foo' - it might not be imported or defined.\nsrc/b.ts(1,1): error TS99999: Unknown.\n"
+        );
+    }
+
+    #[test]
+    fn test_continuation_lines() {
+        let input = "src/a.ts(3,7): error TS2322: Type 'A' is not assignable to type 'B'.\n  Type 'string' is not assignable to type 'number'.\n";
+        let output = run(input);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[1], "  Type 'string' is not assignable to type 'number'.");
+        assert_eq!(lines[2], "  ● I was expecting a type matching 'B' but instead you passed 'A'.");
+        assert_eq!(lines[3], "    ● I was expecting a type matching 'number' but instead you passed 'string'.");
+    }
+
+    #[test]
+    fn test_pretty_format() {
+        let input = "\x1b[96msrc/a.ts\x1b[0m:\x1b[93m3\x1b[0m:\x1b[93m7\x1b[0m - \x1b[91merror\x1b[0m\x1b[90m TS2304: \x1b[0mCannot find name 'foo'.\n\n\x1b[7m3\x1b[0m foo();\n\x1b[7m \x1b[0m \x1b[91m~~~\x1b[0m\n\nFound 1 error.\n";
+        let output = run(input);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[1],
+            "  \x1b[36m● I can't find 'foo' - it might not be imported or defined.\x1b[0m"
+        );
+        assert_eq!(lines[2], "");
+        assert_eq!(lines.len(), input.lines().count() + 1);
+    }
+
+    #[test]
+    fn test_uses_style() {
+        let mut style = Style::default();
+        style.set_template("[TS{code}] {translation}");
+        let mut output = Vec::new();
+        annotate(&ERRORS, &style, "error TS2304: Cannot find name 'foo'.\n".as_bytes(), &mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("\n  [TS2304] I can't find 'foo'"));
+    }
+
+    #[test]
+    fn test_global_error_without_location() {
+        let output = run("error TS2304: Cannot find name 'foo'.\n");
+        assert!(output.contains("● I can't find 'foo'"));
+    }
+}
@@ -0,0 +1,292 @@
+/// How far [`simplify`] lets a type grow before eliding parts of it.
+#[derive(Clone, Debug)]
+pub struct TypeLimits {
+    /// Object types nested deeper than this become `{ ... }`
+    pub max_depth: usize,
+    /// Union members kept before the rest is summarised
+    pub max_union: usize,
+    /// Properties kept per object type before the rest is summarised
+    pub max_properties: usize,
+    /// Point out the first differing property in TS2322/TS2345
+    pub highlight_difference: bool,
+}
+
+impl Default for TypeLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 2,
+            max_union: 5,
+            max_properties: 8,
+            highlight_difference: true,
+        }
+    }
+}
+
+/// Elides deep nesting, long unions and large object types in `ty`.
+/// Text that is not a well-formed type is returned mostly unchanged.
+pub fn simplify(ty: &str, limits: &TypeLimits) -> String {
+    simplify_type(ty, 0, limits)
+}
+
+/// Describes the first property of `target` that `source` lacks or types
+/// differently, when both are object literal types.
+pub fn describe_difference(source: &str, target: &str, limits: &TypeLimits) -> Option<String> {
+    let source = object_properties(source)?;
+    let target = object_properties(target)?;
+
+    target.iter().find_map(|(key, expected)| {
+        match source.iter().find(|(k, _)| k.trim_end_matches('?') == key.trim_end_matches('?')) {
+            None if !key.ends_with('?') => Some(format!("'{}' is missing.", key)),
+            Some((_, actual)) if actual != expected => Some(format!(
+                "The difference is in '{}': '{}' instead of '{}'.",
+                key.trim_end_matches('?'),
+                simplify(actual, limits),
+                simplify(expected, limits)
+            )),
+            _ => None,
+        }
+    })
+}
+
+fn simplify_type(text: &str, depth: usize, limits: &TypeLimits) -> String {
+    let members = split_top_level(text, &['|']);
+    let mut out: Vec<String> = members
+        .iter()
+        .take(limits.max_union)
+        .map(|(member, _)| simplify_member(member, depth, limits))
+        .collect();
+    if members.len() > limits.max_union {
+        out.push(format!(" ... {} more", members.len() - limits.max_union));
+    }
+    out.join("|")
+}
+
+/// A single union member: copies it, simplifying the contents of each bracket.
+fn simplify_member(text: &str, depth: usize, limits: &TypeLimits) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = next_open_bracket(rest) {
+        out.push_str(&rest[..open]);
+        let Some(close) = matching_close(rest, open) else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        let bracket = rest.as_bytes()[open] as char;
+        let inner = &rest[open + 1..close];
+        match bracket {
+            '{' if depth >= limits.max_depth && !inner.trim().is_empty() => out.push_str("{ ... }"),
+            '{' => {
+                out.push('{');
+                out.push_str(&simplify_list(inner, depth + 1, &[';', ','], Some(limits.max_properties), limits));
+                out.push('}');
+            }
+            _ => {
+                out.push(bracket);
+                out.push_str(&simplify_list(inner, depth, &[','], None, limits));
+                out.push(rest.as_bytes()[close] as char);
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Object bodies, parameter lists, tuples and type arguments: simplifies the
+/// type of each `name: type` entry (or the entry itself when it has no name).
+fn simplify_list(text: &str, depth: usize, separators: &[char], max: Option<usize>, limits: &TypeLimits) -> String {
+    let entries = split_top_level(text, separators);
+    let filled = entries.iter().filter(|(entry, _)| !entry.trim().is_empty()).count();
+    let keep = max.unwrap_or(filled);
+
+    let mut out = String::new();
+    for (i, (entry, separator)) in entries.iter().enumerate() {
+        if i == keep && filled > keep {
+            out.push_str(&format!(" ... {} more ", filled - keep));
+            break;
+        }
+        match find_top_level(entry, ':') {
+            Some(colon) => {
+                out.push_str(&entry[..=colon]);
+                out.push_str(&simplify_type(&entry[colon + 1..], depth, limits));
+            }
+            None => out.push_str(&simplify_type(entry, depth, limits)),
+        }
+        if let Some(separator) = separator {
+            out.push(*separator);
+        }
+    }
+    out
+}
+
+/// `name -> type` for each property of an object literal type.
+fn object_properties(ty: &str) -> Option<Vec<(String, String)>> {
+    let ty = ty.trim();
+    if !ty.starts_with('{') || matching_close(ty, 0) != Some(ty.len() - 1) {
+        return None;
+    }
+    Some(
+        split_top_level(&ty[1..ty.len() - 1], &[';', ','])
+            .into_iter()
+            .filter_map(|(entry, _)| {
+                let colon = find_top_level(entry, ':')?;
+                Some((entry[..colon].trim().to_string(), entry[colon + 1..].trim().to_string()))
+            })
+            .collect(),
+    )
+}
+
+/// Splits at `separators` outside brackets and string literals, keeping the separator that ended each piece.
+fn split_top_level<'a>(text: &'a str, separators: &[char]) -> Vec<(&'a str, Option<char>)> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    scan_top_level(text, |i, c| {
+        if separators.contains(&c) {
+            pieces.push((&text[start..i], Some(c)));
+            start = i + c.len_utf8();
+        }
+        false
+    });
+    pieces.push((&text[start..], None));
+    pieces
+}
+
+fn find_top_level(text: &str, wanted: char) -> Option<usize> {
+    let mut found = None;
+    scan_top_level(text, |i, c| {
+        if c == wanted {
+            found = Some(i);
+        }
+        found.is_some()
+    });
+    found
+}
+
+fn next_open_bracket(text: &str) -> Option<usize> {
+    let mut skip_string = None;
+    for (i, c) in text.char_indices() {
+        match skip_string {
+            Some(quote) if c == quote => skip_string = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"' | '`') => skip_string = Some(c),
+            None if is_open(c) => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Index of the bracket closing the one at `open`.
+fn matching_close(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut skip_string = None;
+    let mut prev = '\0';
+    for (i, c) in text[open..].char_indices() {
+        match skip_string {
+            Some(quote) if c == quote && prev != '\\' => skip_string = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"' | '`') => skip_string = Some(c),
+            None if is_open(c) => depth += 1,
+            None if is_close(c, prev) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            None => {}
+        }
+        prev = c;
+    }
+    None
+}
+
+/// Calls `visit` for each character at nesting depth zero until it returns `true`.
+fn scan_top_level(text: &str, mut visit: impl FnMut(usize, char) -> bool) {
+    let mut depth = 0usize;
+    let mut skip_string = None;
+    let mut prev = '\0';
+    for (i, c) in text.char_indices() {
+        match skip_string {
+            Some(quote) if c == quote && prev != '\\' => skip_string = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"' | '`') => skip_string = Some(c),
+            None if is_open(c) => depth += 1,
+            None if is_close(c, prev) => depth = depth.saturating_sub(1),
+            None if depth == 0 && visit(i, c) => return,
+            None => {}
+        }
+        prev = c;
+    }
+}
+
+fn is_open(c: char) -> bool {
+    matches!(c, '{' | '(' | '[' | '<')
+}
+
+/// The `>` of an arrow (`=>`) closes nothing.
+fn is_close(c: char, prev: char) -> bool {
+    matches!(c, '}' | ')' | ']') || (c == '>' && prev != '=')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> TypeLimits {
+        TypeLimits {
+            max_depth: 1,
+            max_union: 3,
+            max_properties: 3,
+            highlight_difference: true,
+        }
+    }
+
+    #[test]
+    fn test_short_types_unchanged() {
+        for ty in ["string", "Promise<number>", "{ a: string; b: number; }", "(x: number) => void", "foo"] {
+            assert_eq!(simplify(ty, &limits()), ty);
+        }
+    }
+
+    #[test]
+    fn test_elides_deep_nesting() {
+        let ty = "{ user: { profile: { name: string; }; }; id: number; }";
+        assert_eq!(simplify(ty, &limits()), "{ user: { ... }; id: number; }");
+    }
+
+    #[test]
+    fn test_elides_long_unions() {
+        let ty = "\"a\" | \"b\" | \"c\" | \"d\" | \"e\"";
+        assert_eq!(simplify(ty, &limits()), "\"a\" | \"b\" | \"c\" | ... 2 more");
+
+        // Only top-level members count; `|` inside strings and brackets is left alone.
+        let ty = "Array<1 | 2 | 3 | 4> | \"x|y\"";
+        assert_eq!(simplify(ty, &limits()), "Array<1 | 2 | 3 | ... 1 more> | \"x|y\"");
+    }
+
+    #[test]
+    fn test_elides_many_properties() {
+        let ty = "{ a: 1; b: 2; c: 3; d: 4; e: 5; }";
+        assert_eq!(simplify(ty, &limits()), "{ a: 1; b: 2; c: 3; ... 2 more }");
+    }
+
+    #[test]
+    fn test_unbalanced_text_kept() {
+        assert_eq!(simplify("{ a: string", &limits()), "{ a: string");
+    }
+
+    #[test]
+    fn test_describe_difference() {
+        let source = "{ id: string; name: string; }";
+        let target = "{ id: number; name: string; }";
+        assert_eq!(
+            describe_difference(source, target, &limits()).unwrap(),
+            "The difference is in 'id': 'string' instead of 'number'."
+        );
+        assert_eq!(
+            describe_difference("{ id: number; }", "{ id: number; name: string; age?: number; }", &limits()).unwrap(),
+            "'name' is missing."
+        );
+        assert!(describe_difference("string", "number", &limits()).is_none());
+    }
+}
@@ -0,0 +1,211 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Directory name used for both the user and workspace override catalogs.
+const CONFIG_DIR: &str = "ts-error-translator";
+
+/// Converts a `file://` URI into a local path, decoding percent escapes.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Drop an optional authority (usually empty or `localhost`).
+    let path = &rest[rest.find('/')?..];
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    let decoded = String::from_utf8(decoded).ok()?;
+
+    // `file:///c:/Users/...` on Windows
+    if cfg!(windows) && decoded.as_bytes().get(2) == Some(&b':') {
+        return Some(PathBuf::from(&decoded[1..]));
+    }
+    Some(PathBuf::from(decoded))
+}
+
+/// Converts an absolute local path into a `file://` URI, percent-encoding as needed.
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Finds the workspace root from `initialize` params, preferring `rootUri`,
+/// then the first workspace folder, then the deprecated `rootPath`.
+pub fn root_from_initialize(params: &Value) -> Option<PathBuf> {
+    if let Some(path) = params.get("rootUri").and_then(Value::as_str).and_then(uri_to_path) {
+        return Some(path);
+    }
+    if let Some(path) = params
+        .get("workspaceFolders")
+        .and_then(Value::as_array)
+        .and_then(|folders| folders.first())
+        .and_then(|folder| folder.get("uri"))
+        .and_then(Value::as_str)
+        .and_then(uri_to_path)
+    {
+        return Some(path);
+    }
+    params.get("rootPath").and_then(Value::as_str).map(PathBuf::from)
+}
+
+/// `$XDG_CONFIG_HOME/ts-error-translator/catalog.{toml,json}`, falling back to `~/.config`.
+pub fn user_catalog_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    find_catalog(&config_home.join(CONFIG_DIR))
+}
+
+/// A directory only the current user can read, for the explanation pages
+/// opened through `window/showDocument`: `$XDG_RUNTIME_DIR/ts-error-translator`,
+/// else `$XDG_CACHE_HOME` (or `~/.cache`) `/ts-error-translator`.
+#[cfg(unix)]
+pub fn private_dir() -> Option<PathBuf> {
+    let base = env_dir("XDG_RUNTIME_DIR")
+        .or_else(|| env_dir("XDG_CACHE_HOME"))
+        .or_else(|| env_dir("HOME").map(|home| home.join(".cache")))?;
+    private_dir_in(&base)
+}
+
+/// `%LOCALAPPDATA%\ts-error-translator`
+#[cfg(not(unix))]
+pub fn private_dir() -> Option<PathBuf> {
+    private_dir_in(&env_dir("LOCALAPPDATA")?)
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// Creates `<base>/ts-error-translator` with mode 0700. `None` when it is a
+/// symlink or belongs to another user, since whoever controls it controls
+/// the files written there.
+#[cfg(unix)]
+fn private_dir_in(base: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    let dir = base.join(CONFIG_DIR);
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir).ok()?;
+    let metadata = std::fs::symlink_metadata(&dir).ok()?;
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::geteuid() } {
+        return None;
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).ok()?;
+    }
+    Some(dir)
+}
+
+#[cfg(not(unix))]
+fn private_dir_in(base: &Path) -> Option<PathBuf> {
+    let dir = base.join(CONFIG_DIR);
+    std::fs::create_dir_all(&dir).ok()?;
+    let metadata = std::fs::symlink_metadata(&dir).ok()?;
+    (metadata.is_dir() && !metadata.file_type().is_symlink()).then_some(dir)
+}
+
+/// `<root>/.ts-error-translator/catalog.{toml,json}`
+pub fn workspace_catalog_path(root: &Path) -> Option<PathBuf> {
+    find_catalog(&root.join(format!(".{}", CONFIG_DIR)))
+}
+
+fn find_catalog(dir: &Path) -> Option<PathBuf> {
+    ["catalog.toml", "catalog.json"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_dir_is_owner_only_and_not_a_symlink() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = std::env::temp_dir().join(format!("ts-error-translator-private-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let dir = private_dir_in(&base).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        // Loosened permissions are tightened again.
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        private_dir_in(&base).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        // A symlink planted in its place is refused.
+        let planted = base.join("planted");
+        std::fs::create_dir(base.join("elsewhere")).unwrap();
+        std::fs::create_dir(&planted).unwrap();
+        std::os::unix::fs::symlink(base.join("elsewhere"), planted.join(CONFIG_DIR)).unwrap();
+        assert!(private_dir_in(&planted).is_none());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_uri_to_path_decodes_escapes() {
+        let path = uri_to_path("file:///home/me/my%20project").unwrap();
+        assert_eq!(path, PathBuf::from("/home/me/my project"));
+    }
+
+    #[test]
+    fn test_path_to_uri_round_trips() {
+        let path = Path::new("/tmp/my project/TS2322.md");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20project/TS2322.md");
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+    }
+
+    #[test]
+    fn test_uri_to_path_rejects_other_schemes() {
+        assert!(uri_to_path("untitled:Untitled-1").is_none());
+    }
+
+    #[test]
+    fn test_root_prefers_root_uri() {
+        let params = json!({
+            "rootUri": "file:///a",
+            "rootPath": "/b",
+            "workspaceFolders": [{"uri": "file:///c", "name": "c"}]
+        });
+        assert_eq!(root_from_initialize(&params), Some(PathBuf::from("/a")));
+    }
+
+    #[test]
+    fn test_root_falls_back_to_workspace_folders() {
+        let params = json!({
+            "rootUri": null,
+            "workspaceFolders": [{"uri": "file:///c", "name": "c"}]
+        });
+        assert_eq!(root_from_initialize(&params), Some(PathBuf::from("/c")));
+    }
+}